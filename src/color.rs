@@ -0,0 +1,407 @@
+use image::Rgb;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A color in CIELAB, where the distance between two colors is about how different they look,
+/// so a threshold means the same thing for a dark title bar as for a bright button.
+#[derive(Copy, Clone, Debug)]
+pub struct Lab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+fn to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_linear(c: f32) -> u8 {
+    let c = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+lazy_static! {
+    // every pixel of every region goes through this, so it's worth not doing the powf each time
+    static ref LINEAR: Vec<f32> = (0..256).map(|x| to_linear(x as f32 / 255.0)).collect();
+}
+
+impl Lab {
+    pub fn from_rgb(color: &Rgb<u8>) -> Lab {
+        let [r, g, b] = color.0;
+        let (r, g, b) = (LINEAR[r as usize], LINEAR[g as usize], LINEAR[b as usize]);
+        // to XYZ, relative to a D65 white
+        let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+        let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+        let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+        let f = |t: f32| {
+            const EPSILON: f32 = 216.0 / 24389.0;
+            if t > EPSILON {
+                t.cbrt()
+            } else {
+                (24389.0 / 27.0 * t + 16.0) / 116.0
+            }
+        };
+        let (x, y, z) = (f(x), f(y), f(z));
+        Lab {
+            l: 116.0 * y - 16.0,
+            a: 500.0 * (x - y),
+            b: 200.0 * (y - z),
+        }
+    }
+
+    /// ΔE*ab: about 2 is as small a difference as anyone can see, and past 50 they're different colors.
+    pub fn delta_e(&self, other: &Lab) -> f32 {
+        ((self.l - other.l).powi(2) + (self.a - other.a).powi(2) + (self.b - other.b).powi(2))
+            .sqrt()
+    }
+}
+
+/// Overwatch's colorblind filters, which tint the whole screen, UI included.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Profile {
+    Off,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl Profile {
+    pub fn parse(name: &str) -> Option<Profile> {
+        match name {
+            "off" => Some(Profile::Off),
+            "protanopia" => Some(Profile::Protanopia),
+            "deuteranopia" => Some(Profile::Deuteranopia),
+            "tritanopia" => Some(Profile::Tritanopia),
+            _ => None,
+        }
+    }
+
+    /// How the filter mixes linear RGB at full strength. Overwatch doesn't publish its filters,
+    /// so these are the usual models (Machado et al. 2009) of each kind of colorblindness,
+    /// and any reference color they get wrong can be set outright under "colors" in config.json.
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            Profile::Off => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            Profile::Protanopia => [
+                [0.152_286, 1.052_583, -0.204_868],
+                [0.114_503, 0.786_281, 0.099_216],
+                [-0.003_882, -0.048_116, 1.051_998],
+            ],
+            Profile::Deuteranopia => [
+                [0.367_322, 0.860_646, -0.227_968],
+                [0.280_085, 0.672_501, 0.047_413],
+                [-0.011_820, 0.042_940, 0.968_881],
+            ],
+            Profile::Tritanopia => [
+                [1.255_528, -0.076_749, -0.178_779],
+                [-0.078_411, 0.930_809, 0.147_602],
+                [0.004_733, 0.691_367, 0.303_900],
+            ],
+        }
+    }
+
+    /// What the filter does to one color, at a strength from 0 to 1 like the slider in the game.
+    fn apply(self, color: Rgb<u8>, strength: f32) -> Rgb<u8> {
+        let linear = color.0.map(|x| LINEAR[x as usize]);
+        let matrix = self.matrix();
+        let mut result = [0; 3];
+        for (i, row) in matrix.iter().enumerate() {
+            let filtered: f32 = row.iter().zip(&linear).map(|(m, c)| m * c).sum();
+            result[i] = from_linear(linear[i] + (filtered - linear[i]) * strength);
+        }
+        Rgb(result)
+    }
+}
+
+/// The parts of the UI that detectors recognize by color.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum UiColor {
+    /// The blue of the timeline up to where the replay's at, which also lights up the controls on game end.
+    TimelinePlayed,
+    TimelineUnplayed,
+    PauseButton,
+    ErrorTitle,
+    ErrorButton,
+    Victory,
+    Defeat,
+    CustomGame,
+    Arcade,
+    QuickPlay,
+    /// The kill feed outlines each hero portrait in its team's color, relative to whoever's being spectated.
+    KillFeedAlly,
+    KillFeedEnemy,
+}
+
+impl UiColor {
+    pub const ALL: [UiColor; 12] = [
+        UiColor::TimelinePlayed,
+        UiColor::TimelineUnplayed,
+        UiColor::PauseButton,
+        UiColor::ErrorTitle,
+        UiColor::ErrorButton,
+        UiColor::Victory,
+        UiColor::Defeat,
+        UiColor::CustomGame,
+        UiColor::Arcade,
+        UiColor::QuickPlay,
+        UiColor::KillFeedAlly,
+        UiColor::KillFeedEnemy,
+    ];
+
+    /// What it's called under "colors" in config.json.
+    pub fn name(self) -> &'static str {
+        match self {
+            UiColor::TimelinePlayed => "timeline_played",
+            UiColor::TimelineUnplayed => "timeline_unplayed",
+            UiColor::PauseButton => "pause_button",
+            UiColor::ErrorTitle => "error_title",
+            UiColor::ErrorButton => "error_button",
+            UiColor::Victory => "victory",
+            UiColor::Defeat => "defeat",
+            UiColor::CustomGame => "custom_game",
+            UiColor::Arcade => "arcade",
+            UiColor::QuickPlay => "quick_play",
+            UiColor::KillFeedAlly => "kill_feed_ally",
+            UiColor::KillFeedEnemy => "kill_feed_enemy",
+        }
+    }
+
+    /// How it looks with no filter, on a screen that shows colors the way the game means them.
+    fn standard(self) -> Rgb<u8> {
+        match self {
+            UiColor::TimelinePlayed => Rgb([46, 181, 229]),
+            UiColor::TimelineUnplayed => Rgb([120, 120, 120]),
+            UiColor::PauseButton => Rgb([193, 193, 193]),
+            UiColor::ErrorTitle => Rgb([28, 33, 46]),
+            UiColor::ErrorButton => Rgb([240, 140, 30]),
+            UiColor::Victory => Rgb([240, 200, 70]),
+            UiColor::Defeat => Rgb([220, 50, 60]),
+            UiColor::CustomGame => Rgb([120, 120, 120]),
+            UiColor::Arcade => Rgb([100, 175, 100]),
+            UiColor::QuickPlay => Rgb([70, 140, 200]),
+            UiColor::KillFeedAlly => Rgb([90, 190, 255]),
+            UiColor::KillFeedEnemy => Rgb([240, 60, 70]),
+        }
+    }
+}
+
+/// The reference color for each part of the UI, under whichever colorblind filter is on.
+#[derive(Clone, Debug)]
+pub struct Palette(HashMap<UiColor, Rgb<u8>>);
+
+impl Palette {
+    pub fn new(profile: Profile, strength: f32) -> Palette {
+        Palette(
+            UiColor::ALL
+                .iter()
+                .map(|&x| (x, profile.apply(x.standard(), strength)))
+                .collect(),
+        )
+    }
+
+    /// Replaces reference colors with ones measured off real screenshots, like `{"victory": [250, 210, 90]}`.
+    pub fn set_colors(&mut self, colors: &Value) -> Result<(), String> {
+        if colors.is_null() {
+            return Ok(());
+        }
+        let colors = colors
+            .as_object()
+            .ok_or("colors must be an object of UI color names to [r, g, b]")?;
+        for (name, value) in colors {
+            let key = UiColor::ALL
+                .iter()
+                .find(|x| x.name() == name)
+                .ok_or_else(|| format!("{} isn't a UI color", name))?;
+            let channels = value
+                .as_array()
+                .filter(|x| x.len() == 3)
+                .and_then(|x| {
+                    x.iter()
+                        .map(|x| x.as_u64().filter(|&x| x <= 255).map(|x| x as u8))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| format!("{} must be [r, g, b], each from 0 to 255", name))?;
+            self.0
+                .insert(*key, Rgb([channels[0], channels[1], channels[2]]));
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, color: UiColor) -> Rgb<u8> {
+        self.0[&color]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new(Profile::Off, 1.0)
+    }
+}
+
+/// How this screen's brightness, gamma, or HDR tone mapping shifts colors, channel by channel,
+/// as measured off UI elements we know the colors of.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Calibration {
+    gain: [f32; 3],
+    offset: [f32; 3],
+}
+
+impl Calibration {
+    pub const NONE: Calibration = Calibration {
+        gain: [1.0, 1.0, 1.0],
+        offset: [0.0, 0.0, 0.0],
+    };
+
+    /// Fits a straight line per channel through pairs of (what a pixel should be, what it was).
+    /// With only one known color there's nothing to fit an offset to, so it's all gain.
+    pub fn fit(samples: &[(Rgb<u8>, Rgb<u8>)]) -> Option<Calibration> {
+        if samples.is_empty() {
+            return None;
+        }
+        let count = samples.len() as f32;
+        let mut result = Calibration::NONE;
+        for channel in 0..3 {
+            let expected = samples.iter().map(|x| x.0[channel] as f32);
+            let observed = samples.iter().map(|x| x.1[channel] as f32);
+            let mean_expected = expected.clone().sum::<f32>() / count;
+            let mean_observed = observed.clone().sum::<f32>() / count;
+            let variance = expected
+                .clone()
+                .map(|x| (x - mean_expected).powi(2))
+                .sum::<f32>();
+            let covariance = expected
+                .zip(observed)
+                .map(|(e, o)| (e - mean_expected) * (o - mean_observed))
+                .sum::<f32>();
+            let (gain, offset) = if variance / count > 100.0 {
+                let gain = covariance / variance;
+                (gain, mean_observed - gain * mean_expected)
+            } else if mean_expected > 0.0 {
+                (mean_observed / mean_expected, 0.0)
+            } else {
+                (1.0, mean_observed)
+            };
+            // anything further off than this is more likely a bad sample than a real screen
+            result.gain[channel] = gain.clamp(0.5, 2.0);
+            result.offset[channel] = offset.clamp(-64.0, 64.0);
+        }
+        Some(result)
+    }
+
+    /// What a color we know should look like on this screen.
+    pub fn apply(&self, color: Rgb<u8>) -> Rgb<u8> {
+        let mut result = [0; 3];
+        for (i, c) in result.iter_mut().enumerate() {
+            let value = color[i] as f32 * self.gain[i] + self.offset[i];
+            *c = value.round().clamp(0.0, 255.0) as u8;
+        }
+        Rgb(result)
+    }
+}
+
+lazy_static! {
+    /// Whatever `install` was given, until the palette is first used.
+    static ref INSTALLED: Mutex<Option<Palette>> = Mutex::new(None);
+    static ref PALETTE: Palette = INSTALLED.lock().unwrap().take().unwrap_or_default();
+    static ref CALIBRATION: Mutex<Calibration> = Mutex::new(Calibration::NONE);
+}
+
+/// Sets up the reference colors from config.json, before anything looks at a frame.
+pub fn install(palette: Palette) {
+    *INSTALLED.lock().unwrap() = Some(palette);
+    lazy_static::initialize(&PALETTE);
+    // if it was already built, it never saw this one
+    if INSTALLED.lock().unwrap().is_some() {
+        panic!("Colors were used before they were set up");
+    }
+}
+
+/// The reference colors, which are the unfiltered ones unless `install` was called first.
+pub fn palette() -> &'static Palette {
+    &PALETTE
+}
+
+/// Compares everything against colors as this screen shows them from now on.
+pub fn calibrate(calibration: Calibration) {
+    debug!(
+        "calibration gain={:?} offset={:?}",
+        calibration.gain, calibration.offset
+    );
+    *CALIBRATION.lock().unwrap() = calibration;
+}
+
+/// What a part of the UI should look like right now, under the colorblind filter and the calibration.
+pub fn expected(color: UiColor) -> Lab {
+    let calibration = *CALIBRATION.lock().unwrap();
+    Lab::from_rgb(&calibration.apply(palette().get(color)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lab_matches_known_values() {
+        let white = Lab::from_rgb(&Rgb([255, 255, 255]));
+        assert!((white.l - 100.0).abs() < 0.1 && white.a.abs() < 0.1 && white.b.abs() < 0.1);
+        let red = Lab::from_rgb(&Rgb([255, 0, 0]));
+        assert!((red.l - 53.24).abs() < 0.1 && (red.a - 80.09).abs() < 0.2);
+        // the same step in RGB is a much bigger step in how it looks for darks than for brights
+        let dark = Lab::from_rgb(&Rgb([20, 20, 20])).delta_e(&Lab::from_rgb(&Rgb([40, 40, 40])));
+        let light =
+            Lab::from_rgb(&Rgb([220, 220, 220])).delta_e(&Lab::from_rgb(&Rgb([240, 240, 240])));
+        assert!(dark > light);
+    }
+
+    #[test]
+    fn filters_move_colors_by_strength() {
+        let blue = UiColor::TimelinePlayed.standard();
+        assert_eq!(Profile::Off.apply(blue, 1.0), blue);
+        assert_eq!(Profile::Deuteranopia.apply(blue, 0.0), blue);
+        let full = Lab::from_rgb(&Profile::Tritanopia.apply(blue, 1.0));
+        let half = Lab::from_rgb(&Profile::Tritanopia.apply(blue, 0.5));
+        let none = Lab::from_rgb(&blue);
+        assert!(full.delta_e(&none) > half.delta_e(&none));
+        assert!(half.delta_e(&none) > 2.0);
+    }
+
+    #[test]
+    fn colors_can_be_set_by_name() {
+        let mut palette = Palette::default();
+        palette
+            .set_colors(&json!({"victory": [250, 210, 90]}))
+            .unwrap();
+        assert_eq!(palette.get(UiColor::Victory), Rgb([250, 210, 90]));
+        assert!(palette.set_colors(&json!({"victroy": [0, 0, 0]})).is_err());
+        assert!(palette
+            .set_colors(&json!({"victory": [0, 0, 256]}))
+            .is_err());
+        assert!(palette.set_colors(&json!({"victory": "gold"})).is_err());
+    }
+
+    #[test]
+    fn calibration_undoes_a_brightness_shift() {
+        // a screen that shows everything at 80% and lifts the blacks a little
+        let shift = |x: Rgb<u8>| Rgb(x.0.map(|c| (c as f32 * 0.8 + 10.0).round() as u8));
+        let known = [
+            UiColor::TimelinePlayed.standard(),
+            UiColor::TimelineUnplayed.standard(),
+        ];
+        let samples = known.iter().map(|&x| (x, shift(x))).collect::<Vec<_>>();
+        let calibration = Calibration::fit(&samples).unwrap();
+        for &color in known.iter() {
+            let fitted = Lab::from_rgb(&calibration.apply(color));
+            assert!(fitted.delta_e(&Lab::from_rgb(&shift(color))) < 1.0);
+        }
+        assert_eq!(Calibration::fit(&[]), None);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::color::{Palette, Profile};
+use crate::detectors::Registry;
+use crate::keybindings::Keybindings;
+use crate::slots::MAX_TEAM_SIZE;
+use crate::window::WindowPattern;
+use log::LevelFilter;
+use serde_json::Value;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// What to do with a replay when none of the badges matched well enough to pick a side.
+/// Recording just "me" skips it either way, since there's nobody else it should record.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SideFallback {
+    /// Record all twelve perspectives, so nothing is lost.
+    BothTeams,
+    /// Ask on the console which side to record.
+    Ask,
+    /// Don't record this replay at all.
+    Skip,
+}
+
+impl SideFallback {
+    fn parse(name: &str) -> Option<SideFallback> {
+        match name {
+            "both" => Some(SideFallback::BothTeams),
+            "ask" => Some(SideFallback::Ask),
+            "skip" => Some(SideFallback::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// Settings that can be overridden in "config.json" next to OWReplayRenderer.exe.
+/// Anything left out of the file keeps its default.
+pub struct Config {
+    /// Players per team: 6 like the original game by default, 5 for Overwatch 2, or anything up to 6 for custom games.
+    pub team_size: u8,
+    /// How well a badge has to match (from 0 to 1) before we believe it.
+    pub min_confidence: f32,
+    /// How many more times to look for a badge, further into the replay, before falling back.
+    pub side_retries: u32,
+    pub side_fallback: SideFallback,
+    /// The longest a game could possibly take, after which we stop recording even if
+    /// we never saw it end. Once one perspective has seen the end, the rest use its length instead.
+    pub max_game_length: Duration,
+    /// How far one press of Replay Forward skips.
+    pub forward_step: Duration,
+    /// Replay viewer keybinds, for anyone who doesn't use the defaults.
+    pub keys: Keybindings,
+    /// How to pick out the game's window, for clients with a different title.
+    pub window: WindowPattern,
+    /// How long to wait for that window to show up.
+    pub window_timeout: Duration,
+    /// How much goes in each recording's log file, from "error" up to "trace" for every detector score.
+    pub log_level: LevelFilter,
+    /// Whether to save the screenshots behind each side guess and game-over call, marked up, in "debug_frames".
+    pub debug_frames: bool,
+    /// The font the scores on debug frames are written in.
+    pub debug_font: PathBuf,
+    /// Thresholds for individual detectors, like `{"paused": {"max_distance": 12}}`, checked when loaded.
+    pub detectors: Value,
+    /// Reference colors for the detectors, under whichever of Overwatch's colorblind filters is on.
+    pub palette: Palette,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            team_size: 6,
+            min_confidence: 0.75,
+            side_retries: 2,
+            side_fallback: SideFallback::BothTeams,
+            max_game_length: Duration::from_secs(40 * 60),
+            forward_step: Duration::from_secs(10),
+            keys: Keybindings::default(),
+            window: WindowPattern::overwatch(),
+            window_timeout: Duration::from_secs(60),
+            log_level: LevelFilter::Debug,
+            debug_frames: false,
+            debug_font: PathBuf::from(r"C:\Windows\Fonts\arial.ttf"),
+            detectors: Value::Null,
+            palette: Palette::default(),
+        }
+    }
+}
+
+impl Config {
+    const FILE: &'static str = "config.json";
+
+    pub fn load() -> Config {
+        let mut result = Config::default();
+        let text = match read_to_string(Config::FILE) {
+            Ok(x) => x,
+            Err(_) => return result,
+        };
+        let value: Value = serde_json::from_str(&text)
+            .unwrap_or_else(|e| panic!("Couldn't parse {}: {}", Config::FILE, e));
+
+        if !value["team_size"].is_null() {
+            result.team_size = value["team_size"]
+                .as_u64()
+                .filter(|&x| x >= 1 && x <= MAX_TEAM_SIZE as u64)
+                .map(|x| x as u8)
+                .unwrap_or_else(|| panic!("team_size must be from 1 to {}", MAX_TEAM_SIZE));
+        }
+        if !value["min_confidence"].is_null() {
+            result.min_confidence = value["min_confidence"]
+                .as_f64()
+                .expect("min_confidence must be a number")
+                as f32;
+        }
+        if !value["side_retries"].is_null() {
+            result.side_retries = value["side_retries"]
+                .as_u64()
+                .expect("side_retries must be a whole number")
+                as u32;
+        }
+        if !value["side_fallback"].is_null() {
+            result.side_fallback = value["side_fallback"]
+                .as_str()
+                .and_then(SideFallback::parse)
+                .expect(r#"side_fallback must be "both", "ask", or "skip""#);
+        }
+        if !value["max_game_minutes"].is_null() {
+            let minutes = value["max_game_minutes"]
+                .as_u64()
+                .expect("max_game_minutes must be a whole number");
+            result.max_game_length = Duration::from_secs(minutes * 60);
+        }
+        if !value["forward_step_seconds"].is_null() {
+            let seconds = value["forward_step_seconds"]
+                .as_u64()
+                .filter(|&x| x > 0)
+                .expect("forward_step_seconds must be a whole number above 0");
+            result.forward_step = Duration::from_secs(seconds);
+        }
+        if !value["keys"].is_null() {
+            result.keys = Keybindings::parse(&value["keys"])
+                .unwrap_or_else(|e| panic!("Bad keys in {}: {}", Config::FILE, e));
+        }
+        if !value["window"].is_null() {
+            result.window = WindowPattern::parse(&value["window"])
+                .unwrap_or_else(|e| panic!("Bad window in {}: {}", Config::FILE, e));
+        }
+        if !value["window_timeout_seconds"].is_null() {
+            let seconds = value["window_timeout_seconds"]
+                .as_u64()
+                .expect("window_timeout_seconds must be a whole number");
+            result.window_timeout = Duration::from_secs(seconds);
+        }
+        if !value["log_level"].is_null() {
+            result.log_level = value["log_level"]
+                .as_str()
+                .and_then(|x| x.parse().ok())
+                .expect(r#"log_level must be "error", "warn", "info", "debug", or "trace""#);
+        }
+        if !value["debug_frames"].is_null() {
+            result.debug_frames = value["debug_frames"]
+                .as_bool()
+                .expect("debug_frames must be true or false");
+        }
+        if !value["debug_font"].is_null() {
+            result.debug_font = value["debug_font"]
+                .as_str()
+                .map(PathBuf::from)
+                .expect("debug_font must be the path to a .ttf file");
+        }
+        Registry::default()
+            .configure(&value["detectors"])
+            .unwrap_or_else(|e| panic!("Bad detectors in {}: {}", Config::FILE, e));
+        result.detectors = value["detectors"].clone();
+        let profile = match value["colorblind"] {
+            Value::Null => Profile::Off,
+            ref x => x.as_str().and_then(Profile::parse).expect(
+                r#"colorblind must be "off", "protanopia", "deuteranopia", or "tritanopia""#,
+            ),
+        };
+        let strength = match value["colorblind_strength"] {
+            Value::Null => 100.0,
+            ref x => x
+                .as_f64()
+                .filter(|x| (0.0..=100.0).contains(x))
+                .expect("colorblind_strength must be from 0 to 100"),
+        };
+        result.palette = Palette::new(profile, strength as f32 / 100.0);
+        result
+            .palette
+            .set_colors(&value["colors"])
+            .unwrap_or_else(|e| panic!("Bad colors in {}: {}", Config::FILE, e));
+        result
+    }
+}
@@ -0,0 +1,145 @@
+/// A rectangle on the desktop, in physical pixels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Every position we click is measured on a 1080p screenshot of the game.
+const DESIGN_WIDTH: i32 = 1920;
+const DESIGN_HEIGHT: i32 = 1080;
+
+/// Where a point on a 1080p screenshot ends up on the desktop, given the part of the window the game draws in.
+/// Windowed, borderless, and fullscreen only differ in where that is and how big.
+pub fn to_desktop(x: i32, y: i32, client: &Rect) -> (i32, i32) {
+    (
+        client.left + x * client.width / DESIGN_WIDTH,
+        client.top + y * client.height / DESIGN_HEIGHT,
+    )
+}
+
+// absolute mouse input runs from 0 to 65535 across the screen, and Windows
+// rounds down when it turns that back into pixels, so we round up to land on the one we meant
+fn normalize(offset: i32, size: i32) -> i32 {
+    if size <= 0 {
+        return 0;
+    }
+    let result = (offset as i64 * 65536 + size as i64 - 1) / size as i64;
+    result.clamp(0, 65535) as i32
+}
+
+/// Turns a point on the desktop into absolute mouse coordinates across the whole virtual desktop,
+/// which spans every monitor and can start left of or above the primary one.
+pub fn to_absolute(x: i32, y: i32, desktop: &Rect) -> (i32, i32) {
+    (
+        normalize(x - desktop.left, desktop.width),
+        normalize(y - desktop.top, desktop.height),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY_1080P: Rect = Rect {
+        left: 0,
+        top: 0,
+        width: 1920,
+        height: 1080,
+    };
+
+    // what Windows does with absolute coordinates
+    fn to_pixel(n: i32, origin: i32, size: i32) -> i32 {
+        origin + (n as i64 * size as i64 / 65536) as i32
+    }
+
+    #[test]
+    fn fullscreen_on_the_primary_monitor_is_unchanged() {
+        assert_eq!(to_desktop(1710, 1003, &PRIMARY_1080P), (1710, 1003));
+        assert_eq!(to_absolute(0, 0, &PRIMARY_1080P), (0, 0));
+        let (x, y) = to_absolute(1919, 1079, &PRIMARY_1080P);
+        assert_eq!((to_pixel(x, 0, 1920), to_pixel(y, 0, 1080)), (1919, 1079));
+    }
+
+    #[test]
+    fn every_pixel_round_trips() {
+        for &size in [800, 1080, 1920, 2560, 3840, 5760, 7680].iter() {
+            for pixel in 0..size {
+                assert_eq!(to_pixel(normalize(pixel, size), 0, size), pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn windowed_scales_into_the_client_area() {
+        let client = Rect {
+            left: 100,
+            top: 50,
+            width: 1280,
+            height: 720,
+        };
+        assert_eq!(to_desktop(0, 0, &client), (100, 50));
+        assert_eq!(to_desktop(960, 540, &client), (740, 410));
+        assert_eq!(to_desktop(1920, 1080, &client), (1380, 770));
+    }
+
+    #[test]
+    fn high_dpi_uses_physical_pixels() {
+        let client = Rect {
+            left: 0,
+            top: 0,
+            width: 3840,
+            height: 2160,
+        };
+        assert_eq!(to_desktop(1710, 1003, &client), (3420, 2006));
+    }
+
+    #[test]
+    fn secondary_monitor_left_of_the_primary() {
+        // the primary monitor starts at 0, so the one to its left is negative
+        let desktop = Rect {
+            left: -1920,
+            top: 0,
+            width: 3840,
+            height: 1080,
+        };
+        let client = Rect {
+            left: -1920,
+            top: 0,
+            width: 1920,
+            height: 1080,
+        };
+        let (x, y) = to_desktop(380, 62, &client);
+        assert_eq!((x, y), (-1540, 62));
+        let (ax, ay) = to_absolute(x, y, &desktop);
+        assert_eq!(to_pixel(ax, desktop.left, desktop.width), x);
+        assert_eq!(to_pixel(ay, desktop.top, desktop.height), y);
+    }
+
+    #[test]
+    fn secondary_monitor_above_and_taller() {
+        let desktop = Rect {
+            left: 0,
+            top: -1440,
+            width: 2560,
+            height: 2520,
+        };
+        let client = Rect {
+            left: 0,
+            top: -1440,
+            width: 2560,
+            height: 1440,
+        };
+        let (x, y) = to_desktop(1690, 380, &client);
+        let (ax, ay) = to_absolute(x, y, &desktop);
+        assert_eq!(to_pixel(ax, desktop.left, desktop.width), x);
+        assert_eq!(to_pixel(ay, desktop.top, desktop.height), y);
+    }
+
+    #[test]
+    fn off_the_desktop_clamps_to_the_edge() {
+        assert_eq!(to_absolute(-10, 2000, &PRIMARY_1080P), (0, 65535));
+    }
+}
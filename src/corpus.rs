@@ -0,0 +1,312 @@
+use crate::config::Config;
+use crate::detectors::registry;
+use crate::image::{Anywhere, Identity, InReplay, ReplaysMenu, Screen, Screenshot};
+use crate::slots::Side;
+use crate::window::Key;
+use image::RgbImage;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Labeled 1080p screenshots that the detectors have to keep getting right, in "fixtures/frames",
+/// with what each one should say about them in "fixtures/frames/labels.json", like
+/// `{"frame": "0001.png", "screen": "replay", "paused": true, "gameover": false, "map": "Ilios"}`.
+/// The badges that "player" names come from live in "fixtures/identities" as "<name>_badge.png".
+/// A frame spectating through a known "slot" like "F3" also has the "side" that slot is on,
+/// if the badge is recognized, since that's how a recording guesses its side.
+const FRAMES: &str = "fixtures/frames";
+const IDENTITIES: &str = "fixtures/identities";
+const LABELS: &str = "labels.json";
+
+/// What the detectors are checked on. Anything a frame's labels leave out isn't checked,
+/// since a list of replays has no pause button and a replay has no list.
+const CHECKED: &[&str] = &[
+    "screen", "paused", "gameover", "occupied", "player", "side", "replays",
+];
+/// Written down to see what the corpus covers, but nothing checks them.
+const NOTES: &[&str] = &["slot", "map", "patch", "note"];
+
+fn screen_name(screen: Screen) -> &'static str {
+    match screen {
+        Screen::ReplayList => "replay_list",
+        Screen::ErrorDialog => "error_dialog",
+        Screen::Replay => "replay",
+        Screen::Unknown => "unknown",
+    }
+}
+
+/// What every detector makes of one frame, keyed the same way as the labels.
+/// A frame is "gameover" if it counts towards the game being over the way recording counts it,
+/// though it takes a few of them in a row to stop a recording.
+pub fn observe(
+    image: &RgbImage,
+    identities: &[Identity],
+    config: &Config,
+    slot: Option<&Key>,
+) -> Value {
+    let anywhere = Screenshot::<Anywhere>::from_image(image.clone());
+    let menu = Screenshot::<ReplaysMenu>::from_image(image.clone());
+    let replay = Screenshot::<InReplay>::from_image(image.clone());
+    let player = identities
+        .iter()
+        .zip(replay.identity_scores(identities))
+        .filter(|(_, score)| *score >= config.min_confidence)
+        .max_by(|a, b| a.1.partial_cmp(&b.1).expect("Couldn't compare floats"))
+        .map(|(identity, _)| identity.name.clone());
+    let side = slot
+        .filter(|_| player.is_some())
+        .and_then(|x| Side::of(x, config.team_size))
+        .map(|x| format!("{:?}", x));
+    let replays = menu
+        .get_replays()
+        .iter()
+        .map(|x| x.game_type().to_string())
+        .collect::<Vec<_>>();
+    json!({
+        "screen": screen_name(anywhere.screen()),
+        "paused": replay.is_definitely_paused(),
+        "gameover": replay.gameover_signals().agree(),
+        "occupied": replay.has_player(),
+        "player": player,
+        "side": side,
+        "replays": replays,
+    })
+}
+
+/// Every label the detectors got wrong, as "what should be vs what was".
+pub fn mismatches(labels: &Value, observed: &Value) -> Vec<String> {
+    CHECKED
+        .iter()
+        .filter_map(|&key| {
+            let expected = labels.get(key)?;
+            let actual = &observed[key];
+            if expected == actual {
+                None
+            } else {
+                Some(format!("{} should be {} but was {}", key, expected, actual))
+            }
+        })
+        .collect()
+}
+
+/// Every value of a checked label that no frame has, since a detector that's never shown
+/// a replay list or a paused replay passes without ever being tested.
+pub fn uncovered(labels: &[Value]) -> Vec<String> {
+    let has = |key: &str, matches: &dyn Fn(&Value) -> bool| {
+        labels.iter().any(|x| x.get(key).map_or(false, matches))
+    };
+    let mut result = vec![];
+    for &screen in ["replay_list", "error_dialog", "replay", "unknown"].iter() {
+        if !has("screen", &|x| x.as_str() == Some(screen)) {
+            result.push(format!("screen = {:?}", screen));
+        }
+    }
+    for &key in ["paused", "gameover", "occupied"].iter() {
+        for &value in [true, false].iter() {
+            if !has(key, &|x| x.as_bool() == Some(value)) {
+                result.push(format!("{} = {}", key, value));
+            }
+        }
+    }
+    if !has("player", &|x| x.is_string()) {
+        result.push("player = a name".to_string());
+    }
+    if !has("player", &|x| x.is_null()) {
+        result.push("player = null".to_string());
+    }
+    for &side in ["Blue", "Red"].iter() {
+        if !has("side", &|x| x.as_str() == Some(side)) {
+            result.push(format!("side = {:?}", side));
+        }
+    }
+    if !has("replays", &|x| {
+        x.as_array().map_or(false, |x| !x.is_empty())
+    }) {
+        result.push("replays = a list".to_string());
+    }
+    result
+}
+
+// the slot a frame was labeled as spectating, if any
+fn slot(labels: &Value) -> Option<Key> {
+    let text = labels.get("slot")?.as_str()?;
+    Some(Key::parse(text).unwrap_or_else(|e| panic!("Bad slot {}: {}", text, e)))
+}
+
+fn read_labels(dir: &Path) -> Vec<Value> {
+    let text = match fs::read_to_string(dir.join(LABELS)) {
+        Ok(x) => x,
+        Err(_) => return vec![],
+    };
+    let labels: Value =
+        serde_json::from_str(&text).unwrap_or_else(|e| panic!("Couldn't parse {}: {}", LABELS, e));
+    labels
+        .as_array()
+        .unwrap_or_else(|| panic!("{} must be a list", LABELS))
+        .clone()
+}
+
+/// Anything typed in is read as JSON if it can be, so `true`, `3`, and `null` mean what they say,
+/// and as plain text otherwise.
+fn ask(key: &str, default: Option<&Value>) -> Option<Value> {
+    match default {
+        Some(x) => println!("{} [{}]:", key, x),
+        None => println!("{} [leave out]:", key),
+    }
+    let answer = crate::read_line();
+    if answer.is_empty() {
+        return default.cloned();
+    }
+    if answer == "-" {
+        return None;
+    }
+    Some(serde_json::from_str(&answer).unwrap_or(Value::String(answer)))
+}
+
+/// Adds screenshots to the corpus, asking what each should be labeled and
+/// suggesting whatever the detectors currently say, so only the wrong ones need typing in.
+pub fn label_frames(paths: &[String]) {
+    let dir = PathBuf::from(FRAMES);
+    fs::create_dir_all(&dir).expect("Failed to create fixtures directory");
+    let config = Config::load();
+    let identities = Identity::load_dir(Path::new(IDENTITIES));
+    let mut labels = read_labels(&dir);
+    println!("Press Enter to keep what's in brackets, type a new value, or - to leave it out.");
+    for path in paths {
+        let screenshot = match Screenshot::<Anywhere>::open(Path::new(path)) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("Skipping {}", e);
+                continue;
+            }
+        };
+        println!("{}:", path);
+        for (name, reading) in registry().run_all(screenshot.image()) {
+            println!(
+                "  {} = {} ({:.2} sure)",
+                name, reading.value, reading.confidence
+            );
+        }
+        // the slot first, since the side we'd guess depends on it
+        let mut entry = json!({});
+        if let Some(value) = ask("slot", None) {
+            entry["slot"] = value;
+        }
+        let observed = observe(
+            screenshot.image(),
+            &identities,
+            &config,
+            slot(&entry).as_ref(),
+        );
+        for &key in CHECKED.iter() {
+            if let Some(value) = ask(key, Some(&observed[key])) {
+                entry[key] = value;
+            }
+        }
+        for &key in NOTES.iter().filter(|&&x| x != "slot") {
+            if let Some(value) = ask(key, None) {
+                entry[key] = value;
+            }
+        }
+
+        for problem in mismatches(&entry, &observed) {
+            println!("The detectors get this one wrong: {}", problem);
+        }
+
+        let frame = format!("{:04}.png", labels.len() + 1);
+        fs::copy(path, dir.join(&frame)).expect("Couldn't copy frame into fixtures");
+        entry["frame"] = json!(frame);
+        labels.push(entry);
+        let text = serde_json::to_string_pretty(&labels).expect("Couldn't serialize labels");
+        fs::write(dir.join(LABELS), text).expect("Couldn't write labels");
+        println!("Saved as {}.", frame);
+    }
+    let missing = uncovered(&labels);
+    if !missing.is_empty() {
+        println!(
+            "The corpus still needs frames labeled {}.",
+            missing.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus() -> Vec<Value> {
+        read_labels(&Path::new(env!("CARGO_MANIFEST_DIR")).join(FRAMES))
+    }
+
+    #[test]
+    fn detectors_match_the_corpus() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let dir = root.join(FRAMES);
+        let identities = Identity::load_dir(&root.join(IDENTITIES));
+        let config = Config::load();
+        let mut failures = vec![];
+        for labels in corpus() {
+            let frame = labels["frame"].as_str().expect("Every label needs a frame");
+            let screenshot = match Screenshot::<Anywhere>::open(&dir.join(frame)) {
+                Ok(x) => x,
+                Err(e) => {
+                    failures.push(e);
+                    continue;
+                }
+            };
+            let observed = observe(
+                screenshot.image(),
+                &identities,
+                &config,
+                slot(&labels).as_ref(),
+            );
+            for problem in mismatches(&labels, &observed) {
+                failures.push(format!("{}: {}", frame, problem));
+            }
+        }
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    // until the corpus has a frame for everything, with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn corpus_covers_every_label_value() {
+        let missing = uncovered(&corpus());
+        assert!(
+            missing.is_empty(),
+            "no frame is labeled {}",
+            missing.join(", ")
+        );
+    }
+
+    #[test]
+    fn missing_label_values_are_listed() {
+        let labels = vec![
+            json!({"screen": "replay", "paused": true, "gameover": false, "occupied": true, "player": "me", "side": "Blue"}),
+            json!({"screen": "replay_list", "replays": ["Quick Play"]}),
+        ];
+        assert_eq!(
+            uncovered(&labels),
+            vec![
+                r#"screen = "error_dialog""#,
+                r#"screen = "unknown""#,
+                "paused = false",
+                "gameover = true",
+                "occupied = false",
+                "player = null",
+                r#"side = "Red""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn only_labeled_keys_are_checked() {
+        let observed = json!({"screen": "replay", "paused": false, "player": null});
+        assert!(mismatches(&json!({"frame": "x.png", "map": "Ilios"}), &observed).is_empty());
+        assert!(mismatches(&json!({"player": null}), &observed).is_empty());
+        assert_eq!(
+            mismatches(&json!({"paused": true}), &observed),
+            vec!["paused should be true but was false".to_string()]
+        );
+    }
+}
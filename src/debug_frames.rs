@@ -0,0 +1,134 @@
+use crate::config::Config;
+use crate::detectors::Mark;
+use crate::image::{OWContext, Screenshot};
+use image::Rgb;
+use imageproc::drawing::{draw_filled_rect_mut, draw_line_segment_mut, draw_text_mut};
+use imageproc::rect::Rect;
+use rusttype::{Font, Scale};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FOUND: Rgb<u8> = Rgb([0, 255, 0]);
+const NOT_FOUND: Rgb<u8> = Rgb([255, 0, 255]);
+const TEXT_SIZE: f32 = 20.0;
+/// The most frames saved under any one name, so a long recording can't fill the disk with them.
+const MAX_PER_NAME: usize = 100;
+
+/// Saves the screenshots behind a decision, with what each detector sampled outlined
+/// and what it measured written beside it, so a wrong call can be seen and reported.
+pub struct FrameDump {
+    dir: PathBuf,
+    font: Option<Font<'static>>,
+    /// Numbers the files, so they sort in the order they were taken.
+    count: Cell<usize>,
+    /// How many have been saved under each name.
+    per_name: RefCell<HashMap<String, usize>>,
+}
+
+impl FrameDump {
+    const DIR: &'static str = "debug_frames";
+
+    /// Keeps frames in a "debug_frames" folder inside the recording subdirectory,
+    /// or `None` if they aren't turned on in config.json.
+    pub fn new(record_dir: &Path, config: &Config) -> Option<FrameDump> {
+        if !config.debug_frames {
+            return None;
+        }
+        let dir = record_dir.join(FrameDump::DIR);
+        fs::create_dir_all(&dir).expect("Failed to create debug frames directory");
+        // without a font, the outlines are still worth having, and the numbers are in the log
+        let font = fs::read(&config.debug_font)
+            .ok()
+            .and_then(|x| Font::from_bytes(x).ok());
+        if font.is_none() {
+            warn!(
+                "Couldn't load {}, so debug frames won't have their scores written on",
+                config.debug_font.display()
+            );
+        }
+        Some(FrameDump {
+            dir,
+            font,
+            count: Cell::new(0),
+            per_name: RefCell::new(HashMap::new()),
+        })
+    }
+
+    fn write(&self, image: &mut image::RgbImage, x: f32, y: f32, text: &str) {
+        let font = match self.font {
+            Some(ref x) => x,
+            None => return,
+        };
+        let scale = Scale::uniform(TEXT_SIZE);
+        let width = font
+            .layout(text, scale, rusttype::point(0.0, 0.0))
+            .filter_map(|x| x.pixel_bounding_box())
+            .map(|x| x.max.x)
+            .max()
+            .unwrap_or(0);
+        let x = (x.max(0.0) as u32).min(image.width().saturating_sub(width as u32 + 4));
+        let y = (y.max(0.0) as u32).min(image.height() - TEXT_SIZE as u32 - 4);
+        // a dark box behind, so it reads over anything
+        draw_filled_rect_mut(
+            image,
+            Rect::at(x as i32, y as i32).of_size(width.max(1) as u32 + 4, TEXT_SIZE as u32 + 4),
+            Rgb([0, 0, 0]),
+        );
+        draw_text_mut(image, Rgb([255, 255, 255]), x + 2, y + 2, scale, font, text);
+    }
+
+    /// Saves a copy of the screenshot with each mark drawn on it, and a caption across the top.
+    pub fn save<C: OWContext>(
+        &self,
+        name: &str,
+        screenshot: &Screenshot<C>,
+        marks: &[Mark],
+        caption: &str,
+    ) {
+        {
+            let mut per_name = self.per_name.borrow_mut();
+            let saved = per_name.entry(name.to_string()).or_insert(0);
+            *saved += 1;
+            if *saved > MAX_PER_NAME {
+                return;
+            }
+            if *saved == MAX_PER_NAME {
+                warn!(
+                    "Saved {} debug frames for {}, so that's the last one",
+                    MAX_PER_NAME, name
+                );
+            }
+        }
+        let mut image = screenshot.image().clone();
+        for mark in marks {
+            let color = if mark.found { FOUND } else { NOT_FOUND };
+            let corners = mark.outline.len();
+            for i in 0..corners {
+                let (x0, y0) = mark.outline[i];
+                let (x1, y1) = mark.outline[(i + 1) % corners];
+                // two pixels wide, so thin regions still stand out
+                draw_line_segment_mut(&mut image, (x0, y0), (x1, y1), color);
+                draw_line_segment_mut(
+                    &mut image,
+                    (x0 - 1.0, y0 - 1.0),
+                    (x1 - 1.0, y1 - 1.0),
+                    color,
+                );
+            }
+            let left = mark.outline.iter().map(|x| x.0).fold(f32::MAX, f32::min);
+            let top = mark.outline.iter().map(|x| x.1).fold(f32::MAX, f32::min);
+            self.write(&mut image, left, top - TEXT_SIZE - 6.0, &mark.label);
+        }
+        self.write(&mut image, 0.0, 0.0, caption);
+
+        let count = self.count.get() + 1;
+        self.count.set(count);
+        let path = self.dir.join(format!("{:04}_{}.png", count, name));
+        debug!("debug_frame path={} caption={:?}", path.display(), caption);
+        image
+            .save(&path)
+            .unwrap_or_else(|e| warn!("Couldn't save {}: {}", path.display(), e));
+    }
+}
@@ -0,0 +1,1206 @@
+use crate::color::{expected, palette, Calibration, Lab, UiColor};
+use crate::image::{badge_outline, warp_username_badge};
+use crate::kill_feed::KillFeed;
+use image::imageops::grayscale;
+use image::{GenericImageView, Rgb, RgbImage, SubImage};
+use imageproc::stats::histogram;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// A rectangle that a detector samples, on a 1080p screenshot.
+#[derive(Copy, Clone, Debug)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Region {
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn view<'a>(&self, data: &'a RgbImage) -> SubImage<&'a RgbImage> {
+        data.view(self.x, self.y, self.width, self.height)
+    }
+
+    pub fn outline(&self) -> Vec<(f32, f32)> {
+        let (left, top) = (self.x as f32, self.y as f32);
+        let (right, bottom) = (left + self.width as f32, top + self.height as f32);
+        vec![(left, top), (right, top), (right, bottom), (left, bottom)]
+    }
+}
+
+/// What a detector looked at and what it made of it, to draw onto a debug frame.
+#[derive(Clone, Debug)]
+pub struct Mark {
+    /// The corners of what was sampled, in order around the edge.
+    pub outline: Vec<(f32, f32)>,
+    pub label: String,
+    /// Whether it counted as seeing what it was looking for.
+    pub found: bool,
+}
+
+/// What a detector made of one frame.
+pub struct Reading<T> {
+    pub value: T,
+    /// How far the measurements were from the threshold, from 0 for a coin flip to 1 for no doubt at all.
+    pub confidence: f32,
+    pub marks: Vec<Mark>,
+}
+
+/// One thing we can tell from a single frame, by sampling a few parts of it.
+pub trait Detector {
+    type Output: Into<Value> + Clone;
+
+    /// What it's called in config.json, the log, and benchmarks.
+    fn name(&self) -> &'static str;
+
+    /// The parts of the frame it reads.
+    fn regions(&self) -> Vec<Region>;
+
+    fn detect(&self, frame: &RgbImage) -> Reading<Self::Output>;
+
+    /// The thresholds that can be tuned under its name in the "detectors" section of config.json.
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)> {
+        vec![]
+    }
+}
+
+/// Any detector, with its output as JSON, so different kinds can be listed and run together.
+pub trait AnyDetector {
+    fn name(&self) -> &'static str;
+    fn regions(&self) -> Vec<Region>;
+    fn detect_any(&self, frame: &RgbImage) -> Reading<Value>;
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)>;
+}
+
+impl<D: Detector> AnyDetector for D {
+    fn name(&self) -> &'static str {
+        Detector::name(self)
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        Detector::regions(self)
+    }
+
+    fn detect_any(&self, frame: &RgbImage) -> Reading<Value> {
+        let reading = self.detect(frame);
+        Reading {
+            value: reading.value.into(),
+            confidence: reading.confidence,
+            marks: reading.marks,
+        }
+    }
+
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)> {
+        Detector::settings(self)
+    }
+}
+
+// in CIELAB, so distances are about how different colors look, not how far apart their numbers are.
+// Thresholds used to be sums of RGB channel differences; each "was N" below is that old sum, and
+// the new one is what moving the reference color N along a single channel comes to on average,
+// for the nearest of the colors it's used with, to the nearest half
+fn color_distance(pixel: &Rgb<u8>, color: &Lab) -> f32 {
+    Lab::from_rgb(pixel).delta_e(color)
+}
+
+fn mean_color_distance(img: &SubImage<&RgbImage>, color: UiColor) -> f32 {
+    let color = expected(color);
+    let (sum, count) = img
+        .pixels()
+        .map(|x| color_distance(&x.2, &color))
+        .fold((0.0, 0), |(sum, count), new| (sum + new, count + 1));
+    sum / (count as f32)
+}
+
+// the fraction of pixels within `max_distance` of the color, for things like text that don't fill their box
+fn color_coverage(img: &SubImage<&RgbImage>, color: UiColor, max_distance: f32) -> f32 {
+    let color = expected(color);
+    let (close, count) = img
+        .pixels()
+        .map(|x| color_distance(&x.2, &color) <= max_distance)
+        .fold((0, 0), |(close, count), new| {
+            (close + new as u32, count + 1)
+        });
+    close as f32 / count as f32
+}
+
+/// How sure a threshold comparison is, by how far the measurement landed from the line.
+pub fn margin(measured: f32, threshold: f32) -> f32 {
+    ((measured - threshold).abs() / threshold).min(1.0)
+}
+
+/// The usual kind of detector: measure one region and see which side of a threshold it's on.
+fn compare(
+    name: &str,
+    region: Region,
+    measured: f32,
+    threshold: f32,
+    below: bool,
+) -> Reading<bool> {
+    let value = if below {
+        measured < threshold
+    } else {
+        measured > threshold
+    };
+    let sign = if below { "<" } else { ">" };
+    Reading {
+        value,
+        confidence: margin(measured, threshold),
+        marks: vec![Mark {
+            outline: region.outline(),
+            label: format!("{} {:.2} ({} {})", name, measured, sign, threshold),
+            found: value,
+        }],
+    }
+}
+
+const ERROR_TITLE: Region = Region::new(660, 430, 600, 40);
+const ERROR_BUTTON: Region = Region::new(890, 610, 140, 36);
+
+/// Error dialogs ("this replay is no longer available", "this replay is from an older patch", etc)
+/// are a dark box in the middle of the screen with an orange OK button under the message.
+pub struct ErrorDialog {
+    pub max_title_distance: f32,
+    pub max_button_distance: f32,
+}
+
+impl Detector for ErrorDialog {
+    type Output = bool;
+
+    fn name(&self) -> &'static str {
+        "error_dialog"
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        vec![ERROR_TITLE, ERROR_BUTTON]
+    }
+
+    fn detect(&self, frame: &RgbImage) -> Reading<bool> {
+        let title = mean_color_distance(&ERROR_TITLE.view(frame), UiColor::ErrorTitle);
+        let button = mean_color_distance(&ERROR_BUTTON.view(frame), UiColor::ErrorButton);
+        let title = compare("title", ERROR_TITLE, title, self.max_title_distance, true);
+        let button = compare(
+            "button",
+            ERROR_BUTTON,
+            button,
+            self.max_button_distance,
+            true,
+        );
+        Reading {
+            value: title.value && button.value,
+            confidence: title.confidence.min(button.confidence),
+            marks: title.marks.into_iter().chain(button.marks).collect(),
+        }
+    }
+
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)> {
+        vec![
+            ("max_title_distance", &mut self.max_title_distance),
+            ("max_button_distance", &mut self.max_button_distance),
+        ]
+    }
+}
+
+// This is wall-to-wall magic numbers, sorry about that.
+fn replay_row(index: u32) -> Region {
+    Region::new(70, 428 + index * 40, 1780, 40)
+}
+
+// how close (ΔE) a row's game type has to be to the nearest type's color on average (was 100)
+const GAME_TYPE_DISTANCE: f32 = 45.5;
+
+fn get_game_type(game_type: SubImage<&RgbImage>) -> String {
+    const TYPES: [(UiColor, &str); 3] = [
+        (UiColor::CustomGame, "Custom Game"),
+        (UiColor::Arcade, "Arcade"),
+        (UiColor::QuickPlay, "Quick Play"),
+    ];
+    let mut colors: Vec<(f32, &str)> = TYPES
+        .iter()
+        .map(|&(color, name)| (mean_color_distance(&game_type, color), name))
+        .collect();
+    colors.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let (distance, name) = colors[0];
+    if distance > GAME_TYPE_DISTANCE {
+        "Unknown".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// The rows of the replays list, as the game type of each one that's there.
+/// The list fills from the top row down, so the first row without a replay ends it:
+/// a grey band further down, or without the top row, is something else, like a replay's scenery.
+pub struct ReplayRows {
+    /// How much of a row has to be in the grey band that replay rows are drawn in.
+    pub min_fraction: f32,
+}
+
+impl Detector for ReplayRows {
+    type Output = Vec<String>;
+
+    fn name(&self) -> &'static str {
+        "replay_rows"
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        (0..11).map(replay_row).collect()
+    }
+
+    fn detect(&self, frame: &RgbImage) -> Reading<Vec<String>> {
+        let mut value = vec![];
+        let mut confidence: f32 = 1.0;
+        let mut marks = vec![];
+        for region in Detector::regions(self) {
+            let row = region.view(frame);
+            // grayscale to let us histogram on value
+            let histogram = histogram(&grayscale(&row));
+            let count = histogram.channels[0][100..150].iter().sum::<u32>();
+            let fraction = count as f32 / row.pixels().count() as f32;
+            let grey = fraction > self.min_fraction;
+            let found = grey && value.len() == marks.len();
+            let label = if found {
+                let game_type = get_game_type(row.view(0, 0, 250, 40));
+                value.push(game_type.clone());
+                game_type
+            } else if grey {
+                "below the end of the list".to_string()
+            } else {
+                "no replay".to_string()
+            };
+            confidence = confidence.min(margin(fraction, self.min_fraction));
+            marks.push(Mark {
+                outline: region.outline(),
+                label: format!("{} {:.2}", label, fraction),
+                found,
+            });
+        }
+        Reading {
+            value,
+            confidence,
+            marks,
+        }
+    }
+
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)> {
+        vec![("min_fraction", &mut self.min_fraction)]
+    }
+}
+
+/// Whether there's anyone in the name badge: one with a name on it has plenty of contrast,
+/// and an empty or disconnected one doesn't.
+pub struct HasPlayer {
+    pub min_spread: f32,
+}
+
+impl Detector for HasPlayer {
+    type Output = bool;
+
+    fn name(&self) -> &'static str {
+        "has_player"
+    }
+
+    // the badge is skewed, so this is the box around it
+    fn regions(&self) -> Vec<Region> {
+        let outline = badge_outline();
+        let left = outline.iter().map(|x| x.0).fold(f32::MAX, f32::min);
+        let top = outline.iter().map(|x| x.1).fold(f32::MAX, f32::min);
+        let right = outline.iter().map(|x| x.0).fold(f32::MIN, f32::max);
+        let bottom = outline.iter().map(|x| x.1).fold(f32::MIN, f32::max);
+        vec![Region::new(
+            left as u32,
+            top as u32,
+            (right - left).ceil() as u32,
+            (bottom - top).ceil() as u32,
+        )]
+    }
+
+    fn detect(&self, frame: &RgbImage) -> Reading<bool> {
+        let badge = warp_username_badge(frame);
+        let count = badge.pixels().count() as f32;
+        let mean = badge.pixels().map(|x| x[0] as f32).sum::<f32>() / count;
+        let variance = badge
+            .pixels()
+            .map(|x| (x[0] as f32 - mean).powi(2))
+            .sum::<f32>()
+            / count;
+        let spread = variance.sqrt();
+        let value = spread > self.min_spread;
+        Reading {
+            value,
+            confidence: margin(spread, self.min_spread),
+            marks: vec![Mark {
+                outline: badge_outline(),
+                label: format!("spread {:.1} (> {})", spread, self.min_spread),
+                found: value,
+            }],
+        }
+    }
+
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)> {
+        vec![("min_spread", &mut self.min_spread)]
+    }
+}
+
+// the middle of the pause button
+const PAUSE_BUTTON: Region = Region::new(316, 997, 4, 15);
+
+pub struct Paused {
+    pub max_distance: f32,
+}
+
+impl Detector for Paused {
+    type Output = bool;
+
+    fn name(&self) -> &'static str {
+        "paused"
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        vec![PAUSE_BUTTON]
+    }
+
+    fn detect(&self, frame: &RgbImage) -> Reading<bool> {
+        let distance = mean_color_distance(&PAUSE_BUTTON.view(frame), UiColor::PauseButton);
+        compare("pause", PAUSE_BUTTON, distance, self.max_distance, true)
+    }
+
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)> {
+        vec![("max_distance", &mut self.max_distance)]
+    }
+}
+
+// the timeline runs from the clock to the far right of the controls,
+// blue where it's been played and grey for the rest
+const TIMELINE: Region = Region::new(460, 1003, 1080, 3);
+const TIMELINE_MIDDLE: Region = Region::new(460, 1004, 1080, 1);
+// how close (ΔE) a pixel has to be to the calibrated blue or grey to count as timeline (was 30)
+const TIMELINE_TOLERANCE: f32 = 14.0;
+// the first calibration fit takes pixels up to this far from the uncalibrated blue or grey:
+// halving or doubling them, the most `Calibration::fit` allows, moves them up to about 44
+const CALIBRATION_GATE: f32 = 45.0;
+// fits after the first only take pixels within `TIMELINE_TOLERANCE` of the last fit's colors
+const CALIBRATION_FITS: usize = 3;
+
+pub struct ControlsShown {
+    pub min_coverage: f32,
+}
+
+impl Detector for ControlsShown {
+    type Output = bool;
+
+    fn name(&self) -> &'static str {
+        "controls_shown"
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        vec![TIMELINE]
+    }
+
+    fn detect(&self, frame: &RgbImage) -> Reading<bool> {
+        let timeline = TIMELINE.view(frame);
+        let coverage = color_coverage(&timeline, UiColor::TimelinePlayed, TIMELINE_TOLERANCE)
+            + color_coverage(&timeline, UiColor::TimelineUnplayed, TIMELINE_TOLERANCE);
+        compare("timeline", TIMELINE, coverage, self.min_coverage, false)
+    }
+
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)> {
+        vec![("min_coverage", &mut self.min_coverage)]
+    }
+}
+
+/// How far into the replay the timeline says we are, from 0 to 1, while the controls are showing.
+pub struct TimelineProgress;
+
+impl Detector for TimelineProgress {
+    type Output = f32;
+
+    fn name(&self) -> &'static str {
+        "timeline_progress"
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        vec![TIMELINE_MIDDLE]
+    }
+
+    fn detect(&self, frame: &RgbImage) -> Reading<f32> {
+        let timeline = TIMELINE_MIDDLE.view(frame);
+        let blue = expected(UiColor::TimelinePlayed);
+        let played = timeline
+            .pixels()
+            .filter(|x| color_distance(&x.2, &blue) < TIMELINE_TOLERANCE)
+            .count();
+        let progress = played as f32 / timeline.width() as f32;
+        Reading {
+            value: progress,
+            // it's a measurement, not a call
+            confidence: 1.0,
+            marks: vec![Mark {
+                outline: TIMELINE_MIDDLE.outline(),
+                label: format!("progress {:.3}", progress),
+                found: played > 0,
+            }],
+        }
+    }
+}
+
+// the replay controls' expand button, which only lights up on game end
+const GAMEOVER_CONTROLS: Region = Region::new(1689, 948, 50, 14);
+// the big VICTORY/DEFEAT text across the middle of the screen
+const GAMEOVER_BANNER: Region = Region::new(660, 380, 600, 120);
+// how close (ΔE) to the gold or red counts as banner text (was 60)
+const BANNER_DISTANCE: f32 = 23.5;
+// the played part of the timeline is filled in, so at the end its far right is too
+const GAMEOVER_TIMELINE: Region = Region::new(1520, 1003, 20, 3);
+
+/// This only works bc the controls autoexpand on game end.
+pub struct GameoverControls {
+    pub max_distance: f32,
+}
+
+impl Detector for GameoverControls {
+    type Output = bool;
+
+    fn name(&self) -> &'static str {
+        "gameover_controls"
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        vec![GAMEOVER_CONTROLS]
+    }
+
+    fn detect(&self, frame: &RgbImage) -> Reading<bool> {
+        let distance = mean_color_distance(&GAMEOVER_CONTROLS.view(frame), UiColor::TimelinePlayed);
+        compare(
+            "controls",
+            GAMEOVER_CONTROLS,
+            distance,
+            self.max_distance,
+            true,
+        )
+    }
+
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)> {
+        vec![("max_distance", &mut self.max_distance)]
+    }
+}
+
+pub struct GameoverBanner {
+    pub min_coverage: f32,
+}
+
+impl Detector for GameoverBanner {
+    type Output = bool;
+
+    fn name(&self) -> &'static str {
+        "gameover_banner"
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        vec![GAMEOVER_BANNER]
+    }
+
+    fn detect(&self, frame: &RgbImage) -> Reading<bool> {
+        let banner = GAMEOVER_BANNER.view(frame);
+        // victory is gold and defeat is red
+        let coverage = color_coverage(&banner, UiColor::Victory, BANNER_DISTANCE)
+            .max(color_coverage(&banner, UiColor::Defeat, BANNER_DISTANCE));
+        compare(
+            "banner",
+            GAMEOVER_BANNER,
+            coverage,
+            self.min_coverage,
+            false,
+        )
+    }
+
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)> {
+        vec![("min_coverage", &mut self.min_coverage)]
+    }
+}
+
+pub struct GameoverTimeline {
+    pub max_distance: f32,
+}
+
+impl Detector for GameoverTimeline {
+    type Output = bool;
+
+    fn name(&self) -> &'static str {
+        "gameover_timeline"
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        vec![GAMEOVER_TIMELINE]
+    }
+
+    fn detect(&self, frame: &RgbImage) -> Reading<bool> {
+        let distance = mean_color_distance(&GAMEOVER_TIMELINE.view(frame), UiColor::TimelinePlayed);
+        compare(
+            "timeline",
+            GAMEOVER_TIMELINE,
+            distance,
+            self.max_distance,
+            true,
+        )
+    }
+
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)> {
+        vec![("max_distance", &mut self.max_distance)]
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct GameoverSignals {
+    /// The replay controls have expanded by themselves.
+    pub controls: bool,
+    /// There's a victory or defeat banner on screen.
+    pub banner: bool,
+    /// The timeline is played all the way to the end.
+    pub timeline: bool,
+}
+
+impl GameoverSignals {
+    pub fn count(&self) -> usize {
+        [self.controls, self.banner, self.timeline]
+            .iter()
+            .filter(|&&x| x)
+            .count()
+    }
+
+    /// Whether enough of them agree for this screenshot to count towards the game being over.
+    pub fn agree(&self) -> bool {
+        self.count() >= GAMEOVER_SIGNALS
+    }
+}
+
+/// How many signals have to agree on one screenshot, since any one of them can be fooled by itself.
+pub const GAMEOVER_SIGNALS: usize = 2;
+/// How many screenshots in a row they have to agree on before we believe them.
+pub const GAMEOVER_FRAMES: usize = 3;
+
+/// Decides when the game is over, so one odd screenshot can't end a recording early.
+pub struct GameoverDetector {
+    pub streak: usize,
+}
+
+impl GameoverDetector {
+    pub fn new() -> GameoverDetector {
+        GameoverDetector { streak: 0 }
+    }
+
+    /// Feeds in the next screenshot's signals, and says whether the game is over.
+    pub fn update(&mut self, signals: &GameoverSignals) -> bool {
+        if signals.agree() {
+            self.streak += 1;
+        } else {
+            self.streak = 0;
+        }
+        self.streak >= GAMEOVER_FRAMES
+    }
+}
+
+/// Counts rounds as they end, by the banner between them, which is drawn where the one at the end of the game is.
+pub struct RoundCounter {
+    streak: usize,
+    /// How many rounds are over so far.
+    pub ended: u8,
+}
+
+impl RoundCounter {
+    pub fn new() -> RoundCounter {
+        RoundCounter {
+            streak: 0,
+            ended: 0,
+        }
+    }
+
+    /// Feeds in the next screenshot's signals, and says whether another round just ended.
+    pub fn update(&mut self, signals: &GameoverSignals) -> bool {
+        if signals.banner {
+            self.streak += 1;
+        } else {
+            self.streak = 0;
+        }
+        // the banner stays up a while, but it's still just the one round
+        let ended = self.streak == GAMEOVER_FRAMES;
+        if ended {
+            self.ended += 1;
+        }
+        ended
+    }
+}
+
+impl From<GameoverSignals> for Value {
+    fn from(signals: GameoverSignals) -> Value {
+        json!({
+            "controls": signals.controls,
+            "banner": signals.banner,
+            "timeline": signals.timeline,
+        })
+    }
+}
+
+/// Every sign we know of that the game has ended. Any one of them can be fooled,
+/// so it's up to the caller to decide how many to trust and for how long.
+pub struct Gameover<'a> {
+    controls: &'a GameoverControls,
+    banner: &'a GameoverBanner,
+    timeline: &'a GameoverTimeline,
+}
+
+impl Detector for Gameover<'_> {
+    type Output = GameoverSignals;
+
+    fn name(&self) -> &'static str {
+        "gameover"
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        vec![GAMEOVER_CONTROLS, GAMEOVER_BANNER, GAMEOVER_TIMELINE]
+    }
+
+    fn detect(&self, frame: &RgbImage) -> Reading<GameoverSignals> {
+        let parts = [
+            self.controls.detect(frame),
+            self.banner.detect(frame),
+            self.timeline.detect(frame),
+        ];
+        let value = GameoverSignals {
+            controls: parts[0].value,
+            banner: parts[1].value,
+            timeline: parts[2].value,
+        };
+        let confidence = parts.iter().map(|x| x.confidence).sum::<f32>() / parts.len() as f32;
+        let marks = parts.iter().flat_map(|x| x.marks.clone()).collect();
+        Reading {
+            value,
+            confidence,
+            marks,
+        }
+    }
+}
+
+/// Every detector we have, with the thresholds from config.json.
+pub struct Registry {
+    pub error_dialog: ErrorDialog,
+    pub replay_rows: ReplayRows,
+    pub has_player: HasPlayer,
+    pub paused: Paused,
+    pub controls_shown: ControlsShown,
+    pub timeline_progress: TimelineProgress,
+    pub gameover_controls: GameoverControls,
+    pub gameover_banner: GameoverBanner,
+    pub gameover_timeline: GameoverTimeline,
+    pub kill_feed: KillFeed,
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry {
+            error_dialog: ErrorDialog {
+                max_title_distance: 19.5,  // was 30
+                max_button_distance: 22.5, // was 60
+            },
+            replay_rows: ReplayRows {
+                min_fraction: 1.0 / 3.0,
+            },
+            has_player: HasPlayer { min_spread: 20.0 },
+            paused: Paused { max_distance: 5.5 }, // was 10
+            controls_shown: ControlsShown { min_coverage: 0.8 },
+            timeline_progress: TimelineProgress,
+            gameover_controls: GameoverControls { max_distance: 1.5 }, // was 3
+            gameover_banner: GameoverBanner { min_coverage: 0.15 },
+            gameover_timeline: GameoverTimeline { max_distance: 5.0 }, // was 10
+            kill_feed: KillFeed::new(0.9),
+        }
+    }
+}
+
+impl Registry {
+    pub fn gameover(&self) -> Gameover<'_> {
+        Gameover {
+            controls: &self.gameover_controls,
+            banner: &self.gameover_banner,
+            timeline: &self.gameover_timeline,
+        }
+    }
+
+    fn all_mut(&mut self) -> Vec<&mut dyn AnyDetector> {
+        vec![
+            &mut self.error_dialog,
+            &mut self.replay_rows,
+            &mut self.has_player,
+            &mut self.paused,
+            &mut self.controls_shown,
+            &mut self.timeline_progress,
+            &mut self.gameover_controls,
+            &mut self.gameover_banner,
+            &mut self.gameover_timeline,
+            &mut self.kill_feed,
+        ]
+    }
+
+    /// Applies something like `{"paused": {"max_distance": 12}}` from config.json.
+    pub fn configure(&mut self, settings: &Value) -> Result<(), String> {
+        if settings.is_null() {
+            return Ok(());
+        }
+        let settings = settings
+            .as_object()
+            .ok_or("detectors must be an object of detector names to settings")?;
+        for (name, values) in settings {
+            let detector = self
+                .all_mut()
+                .into_iter()
+                .find(|x| x.name() == name)
+                .ok_or_else(|| format!("{} isn't a detector", name))?;
+            let values = values
+                .as_object()
+                .ok_or_else(|| format!("the settings for {} must be an object", name))?;
+            let mut known = detector.settings();
+            for (key, value) in values {
+                let setting = known
+                    .iter_mut()
+                    .find(|(x, _)| x == key)
+                    .ok_or_else(|| format!("{} has no setting called {}", name, key))?;
+                *setting.1 = value
+                    .as_f64()
+                    .ok_or_else(|| format!("{}.{} must be a number", name, key))?
+                    as f32;
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with every detector in turn, the composite ones included.
+    pub fn each<F: FnMut(&dyn AnyDetector)>(&self, mut f: F) {
+        f(&self.error_dialog);
+        f(&self.replay_rows);
+        f(&self.has_player);
+        f(&self.paused);
+        f(&self.controls_shown);
+        f(&self.timeline_progress);
+        f(&self.gameover());
+        f(&self.kill_feed);
+    }
+
+    /// Runs every detector over the same frame.
+    pub fn run_all(&self, frame: &RgbImage) -> Vec<(&'static str, Reading<Value>)> {
+        let mut result = vec![];
+        self.each(|x| result.push((x.name(), x.detect_any(frame))));
+        result
+    }
+}
+
+/// Measures how this screen shows colors, off the timeline, which is only ever the played blue
+/// and the unplayed grey. `None` unless the controls are showing, since otherwise it's whatever's behind them.
+pub fn calibration(frame: &RgbImage) -> Option<Calibration> {
+    // against the colors as they should be, not as the last calibration said, so it can't drift
+    let known = [UiColor::TimelinePlayed, UiColor::TimelineUnplayed]
+        .iter()
+        .map(|&x| palette().get(x))
+        .collect::<Vec<_>>();
+    let timeline = TIMELINE.view(frame);
+    let pixels = timeline
+        .pixels()
+        .map(|(_, _, x)| (x, Lab::from_rgb(&x)))
+        .collect::<Vec<_>>();
+    // the first fit takes anything the gain limits could explain, sorted by hue alone, since
+    // a dark enough blue is closer to grey than to blue. Each one after only takes what's
+    // close to the colors the last fit expects, so stray pixels drop out
+    let mut fit = Calibration::NONE;
+    let mut samples = vec![];
+    for round in 0..CALIBRATION_FITS {
+        let first = round == 0;
+        let gate = if first {
+            CALIBRATION_GATE
+        } else {
+            TIMELINE_TOLERANCE
+        };
+        let shown = known
+            .iter()
+            .map(|&x| (x, Lab::from_rgb(&fit.apply(x))))
+            .collect::<Vec<_>>();
+        samples = pixels
+            .iter()
+            .filter_map(|(pixel, lab)| {
+                let sort = |x: &Lab| {
+                    if first {
+                        (x.a - lab.a).hypot(x.b - lab.b)
+                    } else {
+                        x.delta_e(lab)
+                    }
+                };
+                shown
+                    .iter()
+                    .min_by(|a, b| {
+                        sort(&a.1)
+                            .partial_cmp(&sort(&b.1))
+                            .expect("Couldn't compare floats")
+                    })
+                    .filter(|x| x.1.delta_e(lab) < gate)
+                    .map(|x| (x.0, *pixel))
+            })
+            .collect();
+        fit = Calibration::fit(&samples)?;
+    }
+    // it's only the timeline if, once calibrated, nearly all of it is one color or the other
+    trace!("calibration samples={}/{}", samples.len(), pixels.len());
+    if samples.len() * 5 < pixels.len() * 4 {
+        return None;
+    }
+    Some(fit)
+}
+
+lazy_static! {
+    /// Whatever `install` was given, until the detectors are built the first time they're used.
+    static ref SETTINGS: Mutex<Option<Value>> = Mutex::new(None);
+    static ref REGISTRY: Registry = {
+        let mut registry = Registry::default();
+        if let Some(settings) = SETTINGS.lock().unwrap().take() {
+            registry
+                .configure(&settings)
+                .unwrap_or_else(|e| panic!("Bad detectors: {}", e));
+            let icons = registry.kill_feed.load_icons(Path::new(KillFeed::DIR));
+            if registry.kill_feed.has_icons() {
+                info!("Reading the kill feed with {} icons.", icons);
+            }
+        }
+        registry
+    };
+}
+
+/// The detectors everything uses, which are the defaults unless `install` was called first.
+pub fn registry() -> &'static Registry {
+    &REGISTRY
+}
+
+/// Sets up the detectors with the settings from config.json, before anything looks at a frame.
+pub fn install(settings: &Value) {
+    *SETTINGS.lock().unwrap() = Some(settings.clone());
+    lazy_static::initialize(&REGISTRY);
+    // if they were already built, they never saw the settings
+    if SETTINGS.lock().unwrap().is_some() {
+        panic!("Detectors were used before they were set up");
+    }
+}
+
+/// Times every detector over some saved 1080p screenshots, and prints how long each takes per frame.
+pub fn benchmark(paths: &[String]) {
+    let frames = paths
+        .iter()
+        .filter_map(|x| match image::open(Path::new(x)) {
+            Ok(x) => Some(x.to_rgb()),
+            Err(e) => {
+                eprintln!("Skipping {}: {}", x, e);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    if frames.is_empty() {
+        println!("Give --benchmark some screenshots to time the detectors on.");
+        return;
+    }
+    // the default settings, but with the kill feed's icons loaded, since they're most of its time
+    install(&Value::Null);
+    const ROUNDS: u32 = 20;
+    let total_frames = frames.len() as u32 * ROUNDS;
+    registry().each(|detector| {
+        let started = Instant::now();
+        for _ in 0..ROUNDS {
+            for frame in &frames {
+                detector.detect_any(frame);
+            }
+        }
+        let each = started.elapsed() / total_frames;
+        println!(
+            "{:>20}: {:>8.3}ms, {} regions",
+            detector.name(),
+            each.as_secs_f64() * 1000.0,
+            detector.regions().len()
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{Anywhere, InReplay, ReplaysMenu, Screen, Screenshot};
+
+    fn blank() -> RgbImage {
+        RgbImage::new(1920, 1080)
+    }
+
+    fn paint(image: &mut RgbImage, region: Region, color: Rgb<u8>) {
+        for y in region.y..region.y + region.height {
+            for x in region.x..region.x + region.width {
+                image.put_pixel(x, y, color);
+            }
+        }
+    }
+
+    #[test]
+    fn pause_button_is_read() {
+        let mut image = blank();
+        let shot = Screenshot::<InReplay>::from_image(image.clone());
+        assert!(!shot.is_definitely_paused());
+        paint(&mut image, PAUSE_BUTTON, Rgb([193, 193, 193]));
+        let shot = Screenshot::<InReplay>::from_image(image);
+        assert!(shot.is_definitely_paused());
+    }
+
+    #[test]
+    fn gameover_signals_are_counted() {
+        let mut image = blank();
+        let shot = Screenshot::<InReplay>::from_image(image.clone());
+        assert_eq!(shot.gameover_signals().count(), 0);
+        paint(&mut image, GAMEOVER_CONTROLS, Rgb([46, 181, 229]));
+        paint(&mut image, GAMEOVER_TIMELINE, Rgb([46, 181, 229]));
+        let shot = Screenshot::<InReplay>::from_image(image.clone());
+        let signals = shot.gameover_signals();
+        assert!(signals.controls && signals.timeline && !signals.banner);
+        assert_eq!(signals.count(), 2);
+        paint(&mut image, GAMEOVER_BANNER, Rgb([240, 200, 70]));
+        let shot = Screenshot::<InReplay>::from_image(image);
+        assert_eq!(shot.gameover_signals().count(), 3);
+    }
+
+    #[test]
+    fn gameover_takes_frames_in_a_row_that_agree() {
+        let signals = |controls, banner, timeline| GameoverSignals {
+            controls,
+            banner,
+            timeline,
+        };
+        let mut gameover = GameoverDetector::new();
+        // one screenshot with everything lit up, then nothing, isn't the end
+        assert!(!gameover.update(&signals(true, true, true)));
+        assert!(!gameover.update(&signals(false, false, false)));
+        // and neither is one signal by itself, however long it lasts
+        for _ in 0..GAMEOVER_FRAMES * 2 {
+            assert!(!gameover.update(&signals(true, false, false)));
+        }
+        assert!(!gameover.update(&signals(false, true, true)));
+        assert!(!gameover.update(&signals(true, false, true)));
+        assert!(gameover.update(&signals(true, true, false)));
+    }
+
+    #[test]
+    fn rounds_end_once_per_banner() {
+        let banner = |banner| GameoverSignals {
+            controls: false,
+            banner,
+            timeline: false,
+        };
+        let mut rounds = RoundCounter::new();
+        let seen = [
+            false, true, true, true, true, true, false, true, false, true, true, true,
+        ]
+        .iter()
+        .filter(|&&x| rounds.update(&banner(x)))
+        .count();
+        assert_eq!(seen, 2);
+        assert_eq!(rounds.ended, 2);
+    }
+
+    #[test]
+    fn replay_rows_are_listed_with_their_game_type() {
+        let mut image = blank();
+        paint(&mut image, replay_row(0), Rgb([120, 120, 120]));
+        paint(&mut image, replay_row(1), Rgb([130, 130, 130]));
+        paint(
+            &mut image,
+            Region::new(70, 468, 250, 40),
+            Rgb([70, 140, 200]),
+        );
+        let shot = Screenshot::<ReplaysMenu>::from_image(image.clone());
+        let types = shot
+            .get_replays()
+            .iter()
+            .map(|x| x.game_type().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec!["Custom Game", "Quick Play"]);
+        let shot = Screenshot::<Anywhere>::from_image(image);
+        assert_eq!(shot.screen(), Screen::ReplayList);
+    }
+
+    #[test]
+    fn grey_away_from_the_top_of_the_list_is_not_a_replay() {
+        let mut image = blank();
+        paint(&mut image, replay_row(3), Rgb([120, 120, 120]));
+        let shot = Screenshot::<Anywhere>::from_image(image.clone());
+        assert_ne!(shot.screen(), Screen::ReplayList);
+        // and a gap ends the list
+        paint(&mut image, replay_row(0), Rgb([120, 120, 120]));
+        let shot = Screenshot::<ReplaysMenu>::from_image(image);
+        assert_eq!(shot.get_replays().len(), 1);
+    }
+
+    #[test]
+    fn blank_screen_is_unknown_and_empty() {
+        let shot = Screenshot::<Anywhere>::from_image(blank());
+        assert_eq!(shot.screen(), Screen::Unknown);
+        let shot = Screenshot::<InReplay>::from_image(blank());
+        assert!(!shot.has_player());
+    }
+
+    #[test]
+    fn settings_move_the_threshold() {
+        let mut image = blank();
+        // about 8 away from the pause button's grey, which the default of 5.5 doesn't allow
+        paint(&mut image, PAUSE_BUTTON, Rgb([170, 170, 170]));
+        let mut registry = Registry::default();
+        assert!(!registry.paused.detect(&image).value);
+        registry
+            .configure(&json!({"paused": {"max_distance": 10}}))
+            .unwrap();
+        let reading = registry.paused.detect(&image);
+        assert!(reading.value);
+        assert!(reading.confidence > 0.0 && reading.confidence < 1.0);
+    }
+
+    #[test]
+    fn calibration_comes_from_the_timeline() {
+        assert_eq!(calibration(&blank()), None);
+        // a dim screen, with the replay half played
+        let mut image = blank();
+        let dim = |x: UiColor| Rgb(palette().get(x).0.map(|c| (c as f32 * 0.7) as u8));
+        paint(
+            &mut image,
+            Region::new(460, 1003, 540, 3),
+            dim(UiColor::TimelinePlayed),
+        );
+        paint(
+            &mut image,
+            Region::new(1000, 1003, 540, 3),
+            dim(UiColor::TimelineUnplayed),
+        );
+        let calibration = calibration(&image).expect("The timeline should be recognized");
+        assert_ne!(calibration, Calibration::NONE);
+    }
+
+    #[test]
+    fn calibration_finds_a_timeline_far_off_the_references() {
+        // too dark for most of it to be anywhere near the uncalibrated colors
+        let mut image = blank();
+        let dark = |x: UiColor| Rgb(palette().get(x).0.map(|c| (c as f32 * 0.55) as u8));
+        paint(
+            &mut image,
+            Region::new(460, 1003, 540, 3),
+            dark(UiColor::TimelinePlayed),
+        );
+        paint(
+            &mut image,
+            Region::new(1000, 1003, 540, 3),
+            dark(UiColor::TimelineUnplayed),
+        );
+        let calibration = calibration(&image).expect("The timeline should be recognized");
+        for &x in [UiColor::TimelinePlayed, UiColor::TimelineUnplayed].iter() {
+            let shown = Lab::from_rgb(&calibration.apply(palette().get(x)));
+            assert!(shown.delta_e(&Lab::from_rgb(&dark(x))) < TIMELINE_TOLERANCE);
+        }
+    }
+
+    // what an old RGB threshold comes to in ΔE, the way the comment on `color_distance` says
+    fn from_rgb_distance(colors: &[UiColor], old: f32) -> f32 {
+        let mut nearest = f32::MAX;
+        for &color in colors {
+            let reference = palette().get(color);
+            let mut total = 0.0;
+            for channel in 0..3 {
+                for &direction in [-1.0, 1.0].iter() {
+                    let mut moved = reference;
+                    moved[channel] =
+                        (reference[channel] as f32 + direction * old).clamp(0.0, 255.0) as u8;
+                    total += color_distance(&moved, &Lab::from_rgb(&reference));
+                }
+            }
+            nearest = nearest.min(total / 6.0);
+        }
+        (nearest * 2.0).round() / 2.0
+    }
+
+    #[test]
+    fn thresholds_match_the_old_rgb_distances() {
+        let registry = Registry::default();
+        let blue = [UiColor::TimelinePlayed];
+        let checks = [
+            (
+                registry.error_dialog.max_title_distance,
+                from_rgb_distance(&[UiColor::ErrorTitle], 30.0),
+            ),
+            (
+                registry.error_dialog.max_button_distance,
+                from_rgb_distance(&[UiColor::ErrorButton], 60.0),
+            ),
+            (
+                GAME_TYPE_DISTANCE,
+                from_rgb_distance(
+                    &[UiColor::CustomGame, UiColor::Arcade, UiColor::QuickPlay],
+                    100.0,
+                ),
+            ),
+            (
+                registry.paused.max_distance,
+                from_rgb_distance(&[UiColor::PauseButton], 10.0),
+            ),
+            (
+                TIMELINE_TOLERANCE,
+                from_rgb_distance(&[UiColor::TimelinePlayed, UiColor::TimelineUnplayed], 30.0),
+            ),
+            (
+                registry.gameover_controls.max_distance,
+                from_rgb_distance(&blue, 3.0),
+            ),
+            (
+                registry.gameover_timeline.max_distance,
+                from_rgb_distance(&blue, 10.0),
+            ),
+            (
+                BANNER_DISTANCE,
+                from_rgb_distance(&[UiColor::Victory, UiColor::Defeat], 60.0),
+            ),
+        ];
+        for (threshold, converted) in checks.iter() {
+            assert_eq!(threshold, converted);
+        }
+    }
+
+    #[test]
+    fn bad_settings_are_refused() {
+        let mut registry = Registry::default();
+        assert!(registry.configure(&Value::Null).is_ok());
+        assert!(registry.configure(&json!({"pasued": {}})).is_err());
+        assert!(registry
+            .configure(&json!({"paused": {"max_distnace": 1}}))
+            .is_err());
+        assert!(registry
+            .configure(&json!({"paused": {"max_distance": "far"}}))
+            .is_err());
+        assert!(registry
+            .configure(&json!({"timeline_progress": {"anything": 1}}))
+            .is_err());
+    }
+
+    #[test]
+    fn every_detector_runs_in_one_pass() {
+        let readings = Registry::default().run_all(&blank());
+        let names = readings.iter().map(|x| x.0).collect::<Vec<_>>();
+        assert!(names.contains(&"paused") && names.contains(&"gameover"));
+        let gameover = &readings.iter().find(|x| x.0 == "gameover").unwrap().1;
+        assert_eq!(gameover.value["banner"], json!(false));
+        assert_eq!(gameover.marks.len(), 3);
+    }
+}
@@ -1,20 +1,38 @@
+use crate::color::{self, Calibration};
+use crate::detectors::{calibration, registry, Detector, GameoverSignals, Reading};
 use image::imageops::grayscale;
-use image::Pixel;
-use image::{GenericImageView, GrayImage, ImageFormat, Rgb, RgbImage, SubImage};
+use image::{GenericImageView, GrayImage, ImageFormat, Rgb, RgbImage};
 use imageproc::geometric_transformations::{warp, Interpolation, Projection};
-use imageproc::stats::histogram;
 use imageproc::template_matching::{find_extremes, match_template, MatchTemplateMethod};
+use serde_json::Value;
+use std::fs::read_dir;
 use std::marker::PhantomData;
+use std::path::Path;
+use std::time::Duration;
 
 pub trait OWContext {}
 
-#[allow(dead_code)]
 pub struct ReplaysMenu;
 impl OWContext for ReplaysMenu {}
 
 pub struct InReplay;
 impl OWContext for InReplay {}
 
+/// For when we don't know where we are, and want the screenshot to tell us.
+pub struct Anywhere;
+impl OWContext for Anywhere {}
+
+/// Where a screenshot says we are, as far as getting to and from replays goes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Screen {
+    ReplayList,
+    ErrorDialog,
+    /// Spectating someone in a replay.
+    Replay,
+    /// The main menu, an empty list, a loading screen, or anything else we can't recognize.
+    Unknown,
+}
+
 pub struct Screenshot<C: OWContext> {
     data: RgbImage,
     marker: PhantomData<C>,
@@ -31,12 +49,60 @@ impl<C: OWContext> Screenshot<C> {
         let data = base64::decode(data).expect("Image data poorly specified!");
         let image = image::load_from_memory_with_format(&data, ImageFormat::PNG)
             .expect("Image data poorly specified!");
-        let image = image.to_rgb();
+        Screenshot::from_image(image.to_rgb())
+    }
+
+    pub fn from_image(data: RgbImage) -> Screenshot<C> {
         Screenshot {
-            data: image,
+            data,
             marker: PhantomData,
         }
     }
+
+    /// Loads a screenshot saved to disk, which has to be 1080p like the ones OBS gives us.
+    pub fn open(path: &Path) -> Result<Screenshot<C>, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("couldn't load {}: {}", path.display(), e))?
+            .to_rgb();
+        if image.dimensions() != (1920, 1080) {
+            return Err(format!(
+                "{} is {}x{}, not 1920x1080",
+                path.display(),
+                image.width(),
+                image.height()
+            ));
+        }
+        Ok(Screenshot::from_image(image))
+    }
+
+    pub fn image(&self) -> &RgbImage {
+        &self.data
+    }
+
+    /// Runs one detector over this screenshot.
+    pub fn detect<D: Detector>(&self, detector: &D) -> Reading<D::Output> {
+        let reading = detector.detect(&self.data);
+        if log_enabled!(log::Level::Trace) {
+            let value: Value = reading.value.clone().into();
+            let marks = reading
+                .marks
+                .iter()
+                .map(|x| x.label.as_str())
+                .collect::<Vec<_>>();
+            trace!(
+                "detector={} value={} confidence={:.2} marks={:?}",
+                detector.name(),
+                value,
+                reading.confidence,
+                marks
+            );
+        }
+        reading
+    }
+
+    pub fn has_error_dialog(&self) -> bool {
+        self.detect(&registry().error_dialog).value
+    }
 }
 
 #[derive(Debug)]
@@ -44,127 +110,266 @@ pub struct Replay {
     game_type: String,
 }
 
-// uses Manhattan distance
-fn mean_color_distance(img: &SubImage<&RgbImage>, color: &Rgb<u8>) -> f32 {
-    let (sum, count) = img
-        .pixels()
-        .map(|x| {
-            let x_color: &Rgb<u8> = &x.2;
-            let deltas = color.map2(x_color, |x1, x2| if x1 > x2 { x1 - x2 } else { x2 - x1 });
-            let delta: f32 = deltas.channels().iter().map(|x| *x as f32).sum();
-            delta
-        })
-        .fold((0.0, 0), |(sum, count), new| (sum + new, count + 1));
-    sum / (count as f32)
-}
-
-#[allow(dead_code)]
-fn get_game_type(game_type: SubImage<&RgbImage>) -> String {
-    use std::collections::HashMap;
-    lazy_static! {
-        static ref TYPES: HashMap<Rgb<u8>, String> = {
-            let mut types = HashMap::new();
-            types.insert(Rgb([120, 120, 120]), "Custom Game".to_string());
-            types.insert(Rgb([100, 175, 100]), "Arcade".to_string());
-            types.insert(Rgb([70, 140, 200]), "Quick Play".to_string());
-            types
-        };
+impl Replay {
+    /// "Quick Play", "Arcade", "Custom Game", or "Unknown".
+    pub fn game_type(&self) -> &str {
+        &self.game_type
     }
-    let mut colors: Vec<(f32, &Rgb<u8>)> = TYPES
-        .keys()
-        .map(|color| (mean_color_distance(&game_type, color), color))
-        .collect();
-    colors.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-    let (distance, color) = colors[0];
-    if distance > 100.0 {
-        "Unknown".to_string()
-    } else {
-        TYPES[color].clone()
-    }
-}
-
-#[allow(dead_code)]
-fn is_replay(row: &SubImage<&RgbImage>) -> bool {
-    // grayscale to let us histogram on value
-    let image = grayscale(row);
-    // build the histogram
-    let histogram = histogram(&image);
-    // get the count between 130 and 150
-    let count: usize = histogram.channels[0][100..150].iter().sum::<u32>() as usize;
-    // get the total count
-    let total = row.pixels().count();
-    // make sure that at least one third are included in that band
-    count * 3 > total
-}
-
-#[allow(dead_code)]
-fn get_replay(row: SubImage<&RgbImage>) -> Option<Replay> {
-    if is_replay(&row) {
-        let game_type = row.view(0, 0, 250, 40);
-        let game_type = get_game_type(game_type);
-        Some(Replay { game_type })
-    } else {
-        None
-    }
-}
-
-#[allow(dead_code)]
+}
+
 impl Screenshot<ReplaysMenu> {
     pub fn get_replays(&self) -> Vec<Replay> {
-        let data = &self.data;
-        // This is wall-to-wall magic numbers, sorry about that.
-        (1..=11)
-            .filter_map(|index| {
-                let offset = (index - 1) * 40;
-                let row = data.view(70, 428 + offset, 1780, 40);
-                get_replay(row)
-            })
+        self.detect(&registry().replay_rows)
+            .value
+            .into_iter()
+            .map(|game_type| Replay { game_type })
             .collect()
     }
 }
 
-fn warp_username_badge(badge: &RgbImage) -> GrayImage {
-    let transform = Projection::from_matrix([
-        0.86979, 0.25266, -465.5, 0.07896, 1.00069, -885.0, 0.0, 0.0, 1.0,
-    ])
-    .unwrap();
+impl Screenshot<Anywhere> {
+    pub fn screen(&self) -> Screen {
+        let screen = if self.has_error_dialog() {
+            Screen::ErrorDialog
+        } else if !self.detect(&registry().replay_rows).value.is_empty() {
+            Screen::ReplayList
+        } else if self.detect(&registry().has_player).value {
+            Screen::Replay
+        } else {
+            Screen::Unknown
+        };
+        debug!("screen={:?}", screen);
+        screen
+    }
+}
+
+// straightens out the name badge, which is drawn skewed in the bottom left
+const BADGE_TRANSFORM: [f32; 9] = [
+    0.86979, 0.25266, -465.5, 0.07896, 1.00069, -885.0, 0.0, 0.0, 1.0,
+];
+const BADGE_WIDTH: u32 = 180;
+const BADGE_HEIGHT: u32 = 40;
+
+pub fn warp_username_badge(badge: &RgbImage) -> GrayImage {
+    let transform = Projection::from_matrix(BADGE_TRANSFORM).unwrap();
     let badge = warp(&badge, &transform, Interpolation::Bicubic, Rgb([0, 0, 0]));
-    let badge = badge.view(0, 0, 180, 40).to_image();
+    let badge = badge.view(0, 0, BADGE_WIDTH, BADGE_HEIGHT).to_image();
     grayscale(&badge)
 }
 
+/// Where the name badge is on screen, as the corners of the skewed box we straighten out.
+pub fn badge_outline() -> Vec<(f32, f32)> {
+    // the transform only skews and shifts, so undoing it is a 2x2 solve
+    let [a, b, c, d, e, f, _, _, _] = BADGE_TRANSFORM;
+    let det = a * e - b * d;
+    let (width, height) = (BADGE_WIDTH as f32, BADGE_HEIGHT as f32);
+    [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+        .iter()
+        .map(|&(u, v)| {
+            let (u, v) = (u - c, v - f);
+            ((e * u - b * v) / det, (a * v - d * u) / det)
+        })
+        .collect()
+}
+
+fn match_badge(actual: &GrayImage, expected: &GrayImage) -> f32 {
+    let overlap = match_template(
+        actual,
+        expected,
+        MatchTemplateMethod::CrossCorrelationNormalized,
+    );
+    let extremes = find_extremes(&overlap);
+    extremes.max_value
+}
+
+/// The name badge from one screenshot.
+pub struct Badge(GrayImage);
+
+impl Badge {
+    /// How alike two badges are, from 0 to 1.
+    /// If pressing an F-key doesn't change the badge, the camera didn't go anywhere.
+    pub fn same_player_score(&self, other: &Badge) -> f32 {
+        let score = match_badge(&self.0, &other.0);
+        trace!("detector=same_player score={:.3}", score);
+        score
+    }
+}
+
+/// Templates for the digits of the clock in the replay controls,
+/// cut out of screenshots and saved as "clock_digits/0.png" through "clock_digits/9.png".
+pub struct ClockDigits(Vec<GrayImage>);
+
+impl ClockDigits {
+    const DIR: &'static str = "clock_digits";
+
+    /// `None` unless all ten digits are there.
+    pub fn load() -> Option<ClockDigits> {
+        (0..10)
+            .map(|x| {
+                let path = Path::new(ClockDigits::DIR).join(format!("{}.png", x));
+                image::open(path).ok().map(|x| grayscale(&x.to_rgb()))
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(ClockDigits)
+    }
+}
+
+/// A teammate we might be spectating, recognized by the name badge in their HUD.
+pub struct Identity {
+    pub name: String,
+    badge: GrayImage,
+}
+
+impl Identity {
+    const SUFFIX: &'static str = "_badge.png";
+
+    fn load(path: &Path) -> Option<Identity> {
+        let file_name = path.file_name()?.to_str()?;
+        if !file_name.ends_with(Identity::SUFFIX) {
+            return None;
+        }
+        let name = file_name[..file_name.len() - Identity::SUFFIX.len()].to_string();
+        let badge = match image::open(path) {
+            Ok(x) => warp_username_badge(&x.to_rgb()),
+            Err(e) => {
+                warn!("Couldn't load {}: {}", file_name, e);
+                return None;
+            }
+        };
+        Some(Identity { name, badge })
+    }
+
+    /// Loads every "<name>_badge.png" in the working directory, so the original
+    /// "username_badge.png" is just the identity called "username".
+    pub fn load_all() -> Vec<Identity> {
+        Identity::load_dir(Path::new("."))
+    }
+
+    pub fn load_dir(dir: &Path) -> Vec<Identity> {
+        let entries = match read_dir(dir) {
+            Ok(x) => x,
+            Err(_) => return vec![],
+        };
+        let mut result = entries
+            .filter_map(|x| x.ok())
+            .filter_map(|x| Identity::load(&x.path()))
+            .collect::<Vec<_>>();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        result
+    }
+}
+
 impl Screenshot<InReplay> {
-    pub fn has_me() -> bool {
-        std::fs::metadata("username_badge.png").is_ok()
-    }
-
-    pub fn is_me_score(&self) -> f32 {
-        let data = &self.data;
-        let actual_name_badge = warp_username_badge(data);
-        let expected_name_badge = image::open("username_badge.png").unwrap();
-        let expected_name_badge = warp_username_badge(&expected_name_badge.to_rgb());
-        let overlap = match_template(
-            &actual_name_badge,
-            &expected_name_badge,
-            MatchTemplateMethod::CrossCorrelationNormalized,
-        );
-        let extremes = find_extremes(&overlap);
-        extremes.max_value
+    /// Scores every identity against the badge on screen, in the same order.
+    pub fn identity_scores(&self, identities: &[Identity]) -> Vec<f32> {
+        let actual_name_badge = warp_username_badge(&self.data);
+        let scores = identities
+            .iter()
+            .map(|identity| match_badge(&actual_name_badge, &identity.badge))
+            .collect::<Vec<_>>();
+        trace!("detector=identity scores={:?}", scores);
+        scores
+    }
+
+    /// The name badge of whoever we're spectating, to tell later screenshots whether it's still them.
+    pub fn badge(&self) -> Badge {
+        Badge(warp_username_badge(&self.data))
     }
 
-    pub fn is_gameover(&self) -> bool {
-        let data = &self.data;
-        // this only works bc the controls autoexpand on game end
-        let data = data.view(1689, 948, 50, 14);
-        let distance = mean_color_distance(&data, &Rgb([46, 181, 229]));
-        distance < 3.0
+    pub fn has_player(&self) -> bool {
+        self.detect(&registry().has_player).value
+    }
+
+    pub fn gameover_signals(&self) -> GameoverSignals {
+        self.detect(&registry().gameover()).value
+    }
+
+    // the clock is just right of the pause button, as "MM:SS", while the controls are showing
+    pub fn read_clock(&self, digits: &ClockDigits) -> Option<Duration> {
+        let clock = self.data.view(330, 995, 110, 20).to_image();
+        let clock = grayscale(&clock);
+        // find everywhere each digit matches well
+        let mut found: Vec<(u32, u64, f32)> = vec![];
+        for (digit, template) in digits.0.iter().enumerate() {
+            if template.width() > clock.width() || template.height() > clock.height() {
+                continue;
+            }
+            let scores = match_template(
+                &clock,
+                template,
+                MatchTemplateMethod::CrossCorrelationNormalized,
+            );
+            for (x, _, score) in scores.enumerate_pixels() {
+                if score[0] > 0.9 {
+                    found.push((x, digit as u64, score[0]));
+                }
+            }
+        }
+        // then keep the best match in each spot
+        found.sort_by(|a, b| b.2.partial_cmp(&a.2).expect("Couldn't compare floats"));
+        let width = digits.0[0].width() as i64;
+        let mut chosen: Vec<(u32, u64)> = vec![];
+        for (x, digit, _) in found {
+            if chosen
+                .iter()
+                .all(|&(other, _)| (x as i64 - other as i64).abs() >= width)
+            {
+                chosen.push((x, digit));
+            }
+        }
+        chosen.sort();
+        let time = match chosen.as_slice() {
+            [(_, m1), (_, m2), (_, s1), (_, s2)] if *s1 < 6 => {
+                Some(Duration::from_secs((m1 * 10 + m2) * 60 + s1 * 10 + s2))
+            }
+            _ => None,
+        };
+        trace!(
+            "detector=clock digits={:?} seconds={:?}",
+            chosen.iter().map(|x| x.1).collect::<Vec<_>>(),
+            time.map(|x| x.as_secs())
+        );
+        time
     }
 
-    // we measure with the middle of the pause button
     pub fn is_definitely_paused(&self) -> bool {
-        let data = &self.data;
-        let data = data.view(316, 997, 4, 15);
-        let distance = mean_color_distance(&data, &Rgb([193, 193, 193]));
-        distance < 10.0
+        self.detect(&registry().paused).value
+    }
+
+    pub fn controls_shown(&self) -> bool {
+        self.detect(&registry().controls_shown).value
+    }
+
+    pub fn timeline_progress(&self) -> f32 {
+        self.detect(&registry().timeline_progress).value
+    }
+
+    /// Has every detector compare against colors as this screen shows them, from the timeline.
+    /// Says whether it could, which it can't unless the controls are showing.
+    /// If it can't, they go back to the colors as we know them, rather than keep the last recording's.
+    pub fn calibrate(&self) -> bool {
+        let result = calibration(&self.data);
+        color::calibrate(result.unwrap_or(Calibration::NONE));
+        result.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn badge_outline_straightens_into_the_badge() {
+        // the outline's corners should land on the corners of the straightened badge
+        let transform = Projection::from_matrix(BADGE_TRANSFORM).unwrap();
+        let mut image = RgbImage::new(1920, 1080);
+        let outline = badge_outline();
+        let (x, y) = outline[0];
+        image.put_pixel(x.round() as u32, y.round() as u32, Rgb([255, 255, 255]));
+        let warped = warp(&image, &transform, Interpolation::Nearest, Rgb([0, 0, 0]));
+        let bright = (0..3)
+            .flat_map(|y| (0..3).map(move |x| (x, y)))
+            .any(|(x, y)| warped.get_pixel(x, y)[0] > 0);
+        assert!(bright);
     }
 }
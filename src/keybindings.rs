@@ -0,0 +1,120 @@
+use crate::window::*;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Things we do in the replay viewer, each of which can be bound to whatever key the user likes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    JumpToStart,
+    ReplayForward,
+    Pause,
+    ToggleControls,
+    /// Player focus, numbered 1-12 like the F-keys they default to.
+    SpectatePlayer(u8),
+}
+
+impl Action {
+    /// Names as they're written in config.json, e.g. "jump_to_start" or "spectate_player_7".
+    fn parse(name: &str) -> Option<Action> {
+        match name {
+            "jump_to_start" => Some(Action::JumpToStart),
+            "replay_forward" => Some(Action::ReplayForward),
+            "pause" => Some(Action::Pause),
+            "toggle_controls" => Some(Action::ToggleControls),
+            _ => name
+                .strip_prefix("spectate_player_")?
+                .parse()
+                .ok()
+                .filter(|&x| function_key(x).is_some())
+                .map(Action::SpectatePlayer),
+        }
+    }
+
+    /// Overwatch's defaults, except that Jump to Start and Replay Forward have none,
+    /// so these are the ones we've always asked people to set up.
+    fn default_key(self) -> Key {
+        match self {
+            Action::JumpToStart => ctrl(Left),
+            Action::ReplayForward => ctrl(Right),
+            Action::Pause => ctrl(Char('P')),
+            Action::ToggleControls => Char('N'),
+            Action::SpectatePlayer(n) => function_key(n).expect("No F-key for that player"),
+        }
+    }
+}
+
+/// Which key does what in the replay viewer, from the "keys" section of config.json.
+#[derive(Default)]
+pub struct Keybindings(HashMap<Action, Key>);
+
+impl Keybindings {
+    /// Reads something like `{"pause": "Ctrl+Shift+P", "spectate_player_1": "Num1"}`.
+    /// Actions left out keep their default key.
+    pub fn parse(value: &Value) -> Result<Keybindings, String> {
+        let value = value
+            .as_object()
+            .ok_or("keys must be an object of action names to keys")?;
+        let mut result = Keybindings::default();
+        for (name, chord) in value {
+            let action = Action::parse(name).ok_or_else(|| format!("{} isn't an action", name))?;
+            let chord = chord
+                .as_str()
+                .ok_or_else(|| format!("the key for {} must be a string", name))?;
+            result.0.insert(action, Key::parse(chord)?);
+        }
+        Ok(result)
+    }
+
+    pub fn get(&self, action: Action) -> Key {
+        match self.0.get(&action) {
+            Some(x) => x.clone(),
+            None => action.default_key(),
+        }
+    }
+
+    /// The key that spectates a slot, since slots are named after their default F-keys.
+    pub fn spectate(&self, slot: &Key) -> Key {
+        let number = function_number(slot).expect("Slots are always F-keys");
+        self.get(Action::SpectatePlayer(number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_are_read_by_name() {
+        let keys = Keybindings::parse(&json!({
+            "pause": "Ctrl+Shift+P",
+            "spectate_player_12": "Num1",
+        }))
+        .unwrap();
+        assert_eq!(keys.get(Action::Pause), ctrl(shift(Char('P'))));
+        assert_eq!(keys.get(Action::SpectatePlayer(12)), Numpad(1));
+        assert_eq!(keys.spectate(&F12), Numpad(1));
+        // and the rest keep their defaults
+        assert_eq!(keys.get(Action::JumpToStart), ctrl(Left));
+        assert_eq!(keys.spectate(&F3), F3);
+    }
+
+    #[test]
+    fn unknown_actions_and_keys_are_refused() {
+        for (name, chord) in [
+            ("spectate_player_0", "Num1"),
+            ("spectate_player_13", "Num1"),
+            ("spectate_player_", "Num1"),
+            ("jump", "Home"),
+            ("pause", "F13"),
+            ("pause", "Hyper+P"),
+        ]
+        .iter()
+        {
+            let mut value = json!({});
+            value[*name] = json!(chord);
+            assert!(Keybindings::parse(&value).is_err(), "{}: {}", name, chord);
+        }
+        assert!(Keybindings::parse(&json!({"pause": 3})).is_err());
+        assert!(Keybindings::parse(&json!(["pause"])).is_err());
+    }
+}
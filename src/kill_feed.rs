@@ -0,0 +1,548 @@
+use crate::color::{expected, Lab, UiColor};
+use crate::detectors::{margin, Detector, Mark, Reading, Region};
+use image::imageops::{grayscale, resize, FilterType};
+use image::{GenericImageView, GrayImage, RgbImage};
+use imageproc::template_matching::{match_template, MatchTemplateMethod};
+use serde_json::Value;
+use std::fs::read_dir;
+use std::path::Path;
+use std::time::Duration;
+
+// the feed runs down the top right, newest first, each entry right-aligned in its row
+fn feed_row(index: u32) -> Region {
+    Region::new(1340, 40 + index * 36, 560, 34)
+}
+const FEED_ROWS: u32 = 6;
+// icons are matched at half size, which still tells portraits apart and takes a quarter of the time
+const SCALE: u32 = 2;
+/// How long an entry stays in the feed, with some to spare. After this, the same kill again is a new one.
+const FEED_MEMORY: Duration = Duration::from_secs(10);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum IconKind {
+    Hero,
+    Ability,
+    /// The crit marker beside the ability icon.
+    Headshot,
+}
+
+struct Icon {
+    name: String,
+    kind: IconKind,
+    template: GrayImage,
+}
+
+impl Icon {
+    fn new(file_stem: &str, image: &RgbImage) -> Icon {
+        let (kind, name) = if file_stem == "headshot" {
+            (IconKind::Headshot, file_stem)
+        } else if let Some(name) = file_stem.strip_prefix("ability_") {
+            (IconKind::Ability, name)
+        } else {
+            (IconKind::Hero, file_stem)
+        };
+        let template = grayscale(image);
+        let (width, height) = template.dimensions();
+        Icon {
+            name: name.to_string(),
+            kind,
+            template: resize(
+                &template,
+                (width / SCALE).max(1),
+                (height / SCALE).max(1),
+                FilterType::Triangle,
+            ),
+        }
+    }
+
+    fn load(path: &Path) -> Option<Icon> {
+        if path.extension()? != "png" {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        match image::open(path) {
+            Ok(x) => Some(Icon::new(stem, &x.to_rgb())),
+            Err(e) => {
+                warn!("Couldn't load {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// How wide it is on a 1080p screenshot.
+    fn width(&self) -> u32 {
+        self.template.width() * SCALE
+    }
+
+    fn height(&self) -> u32 {
+        self.template.height() * SCALE
+    }
+}
+
+/// Where an icon turned up in a row, in full-size pixels from the row's top left.
+struct Found<'a> {
+    icon: &'a Icon,
+    x: u32,
+    y: u32,
+    score: f32,
+}
+
+/// Relative to whoever's being spectated, since that's how the feed colors it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Team {
+    Ally,
+    Enemy,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Combatant {
+    pub hero: String,
+    /// `None` when the outline was too far from either team's color to say.
+    pub team: Option<Team>,
+}
+
+impl From<&Combatant> for Value {
+    fn from(combatant: &Combatant) -> Value {
+        json!({
+            "hero": combatant.hero,
+            "team": combatant.team.map(|x| match x {
+                Team::Ally => "ally",
+                Team::Enemy => "enemy",
+            }),
+        })
+    }
+}
+
+/// One entry in the kill feed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kill {
+    /// `None` for falls, self-destructs, and anything else with nobody on the left.
+    pub killer: Option<Combatant>,
+    pub victim: Combatant,
+    pub ability: Option<String>,
+    pub headshot: bool,
+}
+
+impl Kill {
+    /// Whether two readings are the same entry. Only the heroes count, since the small
+    /// ability and crit icons are the likeliest to be misread from one screenshot to the next.
+    fn same(&self, other: &Kill) -> bool {
+        self.killer.as_ref().map(|x| &x.hero) == other.killer.as_ref().map(|x| &x.hero)
+            && self.victim.hero == other.victim.hero
+    }
+}
+
+impl From<Kill> for Value {
+    fn from(kill: Kill) -> Value {
+        json!({
+            "killer": kill.killer.as_ref().map(Value::from),
+            "victim": Value::from(&kill.victim),
+            "ability": kill.ability,
+            "headshot": kill.headshot,
+        })
+    }
+}
+
+/// Reads the kill feed by template matching against icons cut out of 1080p screenshots,
+/// saved in "kill_feed_icons" as "<hero>.png" for portraits, "ability_<name>.png" for
+/// ability icons, and "headshot.png" for the crit marker. Cut them just inside the team-colored outline.
+pub struct KillFeed {
+    icons: Vec<Icon>,
+    pub min_score: f32,
+}
+
+impl KillFeed {
+    pub const DIR: &'static str = "kill_feed_icons";
+    /// How often to read the feed. Matching every icon takes far longer than the rest of a
+    /// look at the screen, and entries stay up long enough that this still sees each one.
+    pub const EVERY: Duration = Duration::from_secs(3);
+
+    pub fn new(min_score: f32) -> KillFeed {
+        KillFeed {
+            icons: vec![],
+            min_score,
+        }
+    }
+
+    /// Loads every icon in `dir`, and says how many there were.
+    pub fn load_icons(&mut self, dir: &Path) -> usize {
+        let entries = match read_dir(dir) {
+            Ok(x) => x,
+            Err(_) => return 0,
+        };
+        self.icons = entries
+            .filter_map(|x| x.ok())
+            .filter_map(|x| Icon::load(&x.path()))
+            .collect();
+        self.icons.sort_by(|a, b| a.name.cmp(&b.name));
+        self.icons.len()
+    }
+
+    /// Without any hero portraits there's nothing to read the feed with.
+    pub fn has_icons(&self) -> bool {
+        self.icons.iter().any(|x| x.kind == IconKind::Hero)
+    }
+
+    /// Every icon that matches somewhere in the row, best first where they overlap, left to right.
+    fn find(&self, row: &GrayImage) -> Vec<Found<'_>> {
+        let mut found = vec![];
+        for icon in &self.icons {
+            let template = &icon.template;
+            if template.width() > row.width() || template.height() > row.height() {
+                continue;
+            }
+            let scores = match_template(
+                row,
+                template,
+                MatchTemplateMethod::CrossCorrelationNormalized,
+            );
+            for (x, y, score) in scores.enumerate_pixels() {
+                if score[0] >= self.min_score {
+                    found.push(Found {
+                        icon,
+                        x: x * SCALE,
+                        y: y * SCALE,
+                        score: score[0],
+                    });
+                }
+            }
+        }
+        // then keep the best match in each spot, like reading the clock
+        found.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .expect("Couldn't compare floats")
+        });
+        let mut chosen: Vec<Found> = vec![];
+        for x in found {
+            if chosen
+                .iter()
+                .all(|other| x.x + x.icon.width() <= other.x || other.x + other.icon.width() <= x.x)
+            {
+                chosen.push(x);
+            }
+        }
+        chosen.sort_by_key(|x| x.x);
+        chosen
+    }
+
+    /// Which team's color outlines a portrait, from the line just under it.
+    fn team(frame: &RgbImage, row: Region, portrait: &Found) -> Option<Team> {
+        let under = Region::new(
+            row.x + portrait.x,
+            row.y + portrait.y + portrait.icon.height(),
+            portrait.icon.width(),
+            2,
+        );
+        if under.y + under.height > frame.height() {
+            return None;
+        }
+        let distance = |color: UiColor| {
+            let color = expected(color);
+            let pixels = under.view(frame).pixels().collect::<Vec<_>>();
+            pixels
+                .iter()
+                .map(|x| Lab::from_rgb(&x.2).delta_e(&color))
+                .sum::<f32>()
+                / pixels.len() as f32
+        };
+        let (ally, enemy) = (
+            distance(UiColor::KillFeedAlly),
+            distance(UiColor::KillFeedEnemy),
+        );
+        if ally.min(enemy) > 25.0 {
+            None
+        } else if ally < enemy {
+            Some(Team::Ally)
+        } else {
+            Some(Team::Enemy)
+        }
+    }
+
+    /// The kill in one row, if there is one, and the best icon score behind it.
+    fn read_row(
+        &self,
+        frame: &RgbImage,
+        region: Region,
+        marks: &mut Vec<Mark>,
+    ) -> (Option<Kill>, f32) {
+        let row = grayscale(&region.view(frame).to_image());
+        let row = resize(
+            &row,
+            region.width / SCALE,
+            region.height / SCALE,
+            FilterType::Triangle,
+        );
+        let found = self.find(&row);
+        for x in &found {
+            let outline = Region::new(
+                region.x + x.x,
+                region.y + x.y,
+                x.icon.width(),
+                x.icon.height(),
+            );
+            marks.push(Mark {
+                outline: outline.outline(),
+                label: format!("{} {:.2}", x.icon.name, x.score),
+                found: true,
+            });
+        }
+        let best = found.iter().map(|x| x.score).fold(0.0, f32::max);
+        let heroes = found
+            .iter()
+            .filter(|x| x.icon.kind == IconKind::Hero)
+            .collect::<Vec<_>>();
+        let victim = match heroes.last() {
+            Some(x) => x,
+            None => return (None, best),
+        };
+        // with only one portrait, it's the victim, since the feed is right-aligned
+        let killer = if heroes.len() > 1 {
+            Some(heroes[0])
+        } else {
+            None
+        };
+        let between = |x: &Found| x.x < victim.x && killer.map_or(true, |killer| x.x > killer.x);
+        let ability = found
+            .iter()
+            .filter(|x| x.icon.kind == IconKind::Ability && between(x))
+            .max_by(|a, b| {
+                a.score
+                    .partial_cmp(&b.score)
+                    .expect("Couldn't compare floats")
+            })
+            .map(|x| x.icon.name.clone());
+        let headshot = found
+            .iter()
+            .any(|x| x.icon.kind == IconKind::Headshot && between(x));
+        let combatant = |x: &Found| Combatant {
+            hero: x.icon.name.clone(),
+            team: KillFeed::team(frame, region, x),
+        };
+        let kill = Kill {
+            killer: killer.map(&combatant),
+            victim: combatant(victim),
+            ability,
+            headshot,
+        };
+        (Some(kill), best)
+    }
+}
+
+impl Detector for KillFeed {
+    type Output = Vec<Kill>;
+
+    fn name(&self) -> &'static str {
+        "kill_feed"
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        (0..FEED_ROWS).map(feed_row).collect()
+    }
+
+    fn detect(&self, frame: &RgbImage) -> Reading<Vec<Kill>> {
+        let mut kills = vec![];
+        let mut confidence: f32 = 1.0;
+        let mut marks = vec![];
+        for region in Detector::regions(self) {
+            let (kill, best) = self.read_row(frame, region, &mut marks);
+            if best > 0.0 {
+                confidence = confidence.min(margin(best, self.min_score));
+            }
+            let label = match kill {
+                Some(ref x) => format!(
+                    "{} > {}",
+                    x.killer.as_ref().map_or("-", |x| x.hero.as_str()),
+                    x.victim.hero
+                ),
+                None => "no kill".to_string(),
+            };
+            marks.push(Mark {
+                outline: region.outline(),
+                label,
+                found: kill.is_some(),
+            });
+            kills.extend(kill);
+        }
+        Reading {
+            value: kills,
+            confidence,
+            marks,
+        }
+    }
+
+    fn settings(&mut self) -> Vec<(&'static str, &mut f32)> {
+        vec![("min_score", &mut self.min_score)]
+    }
+}
+
+/// Every kill seen over one recording, once each, however many screenshots it stayed in the feed for.
+#[derive(Default)]
+pub struct KillLog {
+    events: Vec<(Duration, Kill)>,
+    /// What's still in the feed, and when it was last seen there.
+    on_screen: Vec<(Kill, Duration)>,
+}
+
+impl KillLog {
+    /// Feeds in one screenshot's kill feed, from `at` into the replay.
+    pub fn update(&mut self, at: Duration, kills: &[Kill]) {
+        self.on_screen
+            .retain(|(_, seen)| at.checked_sub(*seen).map_or(true, |x| x < FEED_MEMORY));
+        // each entry on screen can only account for one in this screenshot,
+        // so two of the same kill at once are still two kills
+        let mut unmatched = (0..self.on_screen.len()).collect::<Vec<_>>();
+        for kill in kills {
+            match unmatched
+                .iter()
+                .position(|&i| self.on_screen[i].0.same(kill))
+            {
+                Some(position) => {
+                    let i = unmatched.remove(position);
+                    self.on_screen[i].1 = at;
+                }
+                None => {
+                    debug!("kill at={} {}", at.as_secs(), Value::from(kill.clone()));
+                    self.events.push((at, kill.clone()));
+                    self.on_screen.push((kill.clone(), at));
+                }
+            }
+        }
+    }
+
+    pub fn into_events(self) -> Vec<(Duration, Kill)> {
+        self.events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    // blocky noise, so it survives being shrunk and doesn't match anything but itself
+    fn pattern(seed: u32) -> RgbImage {
+        RgbImage::from_fn(24, 24, |x, y| {
+            let cell = (x / 4) * 7 + (y / 4) * 13 + seed * 31;
+            let value = (cell.wrapping_mul(2_654_435_761) >> 24) as u8;
+            Rgb([value, value, value])
+        })
+    }
+
+    fn stamp(frame: &mut RgbImage, icon: &RgbImage, x: u32, y: u32) {
+        for (dx, dy, pixel) in icon.enumerate_pixels() {
+            frame.put_pixel(x + dx, y + dy, *pixel);
+        }
+    }
+
+    fn underline(frame: &mut RgbImage, x: u32, y: u32, color: UiColor) {
+        let color = crate::color::palette().get(color);
+        for dy in 0..2 {
+            for dx in 0..24 {
+                frame.put_pixel(x + dx, y + 24 + dy, color);
+            }
+        }
+    }
+
+    fn feed() -> KillFeed {
+        let mut feed = KillFeed::new(0.9);
+        feed.icons = vec![
+            Icon::new("ana", &pattern(1)),
+            Icon::new("reaper", &pattern(2)),
+            Icon::new("ability_sleep_dart", &pattern(3)),
+            Icon::new("headshot", &pattern(4)),
+        ];
+        feed
+    }
+
+    #[test]
+    fn a_kill_is_read_from_its_row() {
+        let feed = feed();
+        let mut frame = RgbImage::new(1920, 1080);
+        assert!(feed.detect(&frame).value.is_empty());
+        let row = feed_row(0);
+        stamp(&mut frame, &pattern(1), row.x + 300, row.y + 2);
+        underline(&mut frame, row.x + 300, row.y + 2, UiColor::KillFeedAlly);
+        stamp(&mut frame, &pattern(3), row.x + 380, row.y + 2);
+        stamp(&mut frame, &pattern(4), row.x + 420, row.y + 2);
+        stamp(&mut frame, &pattern(2), row.x + 500, row.y + 2);
+        underline(&mut frame, row.x + 500, row.y + 2, UiColor::KillFeedEnemy);
+        let kills = feed.detect(&frame).value;
+        assert_eq!(
+            kills,
+            vec![Kill {
+                killer: Some(Combatant {
+                    hero: "ana".to_string(),
+                    team: Some(Team::Ally),
+                }),
+                victim: Combatant {
+                    hero: "reaper".to_string(),
+                    team: Some(Team::Enemy),
+                },
+                ability: Some("sleep_dart".to_string()),
+                headshot: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn a_lone_portrait_is_the_victim() {
+        let feed = feed();
+        let mut frame = RgbImage::new(1920, 1080);
+        let row = feed_row(2);
+        stamp(&mut frame, &pattern(2), row.x + 500, row.y + 2);
+        let kills = feed.detect(&frame).value;
+        assert_eq!(kills.len(), 1);
+        assert_eq!(kills[0].killer, None);
+        assert_eq!(kills[0].victim.hero, "reaper");
+        assert_eq!(kills[0].victim.team, None);
+    }
+
+    #[test]
+    fn kills_are_logged_once_while_they_stay_in_the_feed() {
+        let kill = |killer: &str, victim: &str| Kill {
+            killer: Some(Combatant {
+                hero: killer.to_string(),
+                team: None,
+            }),
+            victim: Combatant {
+                hero: victim.to_string(),
+                team: None,
+            },
+            ability: None,
+            headshot: false,
+        };
+        let seconds = Duration::from_secs;
+        let mut log = KillLog::default();
+        log.update(seconds(5), &[kill("ana", "reaper")]);
+        log.update(seconds(6), &[kill("genji", "ana"), kill("ana", "reaper")]);
+        log.update(seconds(8), &[kill("genji", "ana"), kill("ana", "reaper")]);
+        // two at once are two kills, even of the same heroes
+        log.update(
+            seconds(9),
+            &[
+                kill("genji", "mercy"),
+                kill("genji", "mercy"),
+                kill("genji", "ana"),
+            ],
+        );
+        // long enough after the first one left the feed that this is a new kill
+        log.update(seconds(30), &[kill("ana", "reaper")]);
+        let events = log
+            .into_events()
+            .iter()
+            .map(|(at, x)| (at.as_secs(), x.victim.hero.clone()))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            (5, "reaper"),
+            (6, "ana"),
+            (9, "mercy"),
+            (9, "mercy"),
+            (30, "reaper"),
+        ]
+        .into_iter()
+        .map(|(at, x)| (at, x.to_string()))
+        .collect::<Vec<_>>();
+        assert_eq!(events, expected);
+    }
+}
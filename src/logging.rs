@@ -0,0 +1,90 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Where each recording subdirectory keeps its log.
+const FILE: &str = "log.txt";
+
+/// Info and above goes to the console as plain text, the way it always has.
+/// Everything up to the configured level also goes to the log file, one line each, as
+/// `time LEVEL module: event key=value ...` so it can be searched and pulled apart afterwards.
+struct Logger {
+    file: Mutex<Option<File>>,
+    level: Mutex<LevelFilter>,
+}
+
+lazy_static! {
+    static ref LOGGER: Logger = Logger {
+        file: Mutex::new(None),
+        level: Mutex::new(LevelFilter::Debug),
+    };
+}
+
+impl Logger {
+    fn write_file(&self, level: Level, target: &str, message: &str) {
+        if level > *self.level.lock().unwrap() {
+            return;
+        }
+        if let Some(ref mut file) = *self.file.lock().unwrap() {
+            let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+            // "ow_replay_renderer::obs" says no more than "obs"
+            let target = target.rsplit("::").next().unwrap_or(target);
+            let _ = writeln!(file, "{} {:5} {}: {}", time, level, target, message);
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info || metadata.level() <= *self.level.lock().unwrap()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        match record.level() {
+            Level::Error | Level::Warn => eprintln!("{}", message),
+            Level::Info => println!("{}", message),
+            _ => (),
+        }
+        self.write_file(record.level(), record.target(), &message);
+    }
+
+    fn flush(&self) {
+        if let Some(ref mut file) = *self.file.lock().unwrap() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Starts logging to the console, and makes sure a panic ends up in the log file too.
+pub fn init() {
+    log::set_logger(&*LOGGER).expect("Logger was already set");
+    log::set_max_level(LevelFilter::Debug);
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        LOGGER.write_file(Level::Error, "panic", &info.to_string());
+        default_hook(info);
+    }));
+}
+
+/// How much goes in the log file: "error", "warn", "info", "debug", or "trace".
+/// The console always gets info and above.
+pub fn set_level(level: LevelFilter) {
+    *LOGGER.level.lock().unwrap() = level;
+    log::set_max_level(level.max(LevelFilter::Info));
+}
+
+/// Sends the log file to a new recording subdirectory, adding to it if it's already there.
+pub fn log_to(dir: &Path) {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(FILE))
+        .unwrap_or_else(|e| panic!("Couldn't open {}: {}", FILE, e));
+    *LOGGER.file.lock().unwrap() = Some(file);
+}
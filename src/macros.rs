@@ -0,0 +1,362 @@
+use crate::image::Screen;
+use crate::navigation::Game;
+use crate::window::Key;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::time::Duration;
+
+/// The menu sequences we use, written the same way as "macros.txt".
+/// Anything in that file replaces the macro of the same name here.
+const BUILTIN: &str = r#"
+# from the main menu to the replays & highlights screen
+[open_replays_screen]
+wait 200ms
+press Up x4
+press Space
+wait 2s
+
+[open_replays_tab]
+click 380 62
+wait 2s
+
+[open_highlights_tab]
+click 220 62
+wait 2s
+
+# {index} counts down from the top of the list, where clicking the tab leaves the cursor
+[open_replay]
+press Down x{index}
+press Tab
+press Space
+wait 10s
+
+# {code} goes in the import dialog, which opens the replay straight away
+[import_replay]
+click 1690 380
+wait 2s
+type {code}
+press Enter
+wait 2s
+press Space
+wait 10s
+
+[dismiss_error]
+press Escape
+wait 2s
+
+# {index} counts down from the top of the list, where clicking the tab leaves the cursor
+[pick_highlight]
+press Down x{index}
+press Tab
+
+[play_highlight]
+press Space
+wait 2s
+
+[exit_replay]
+wait 2s
+# click to dismiss the controls if they are shown
+click 1710 1003
+press Escape
+press Up x2
+press Space
+wait 2s
+"#;
+
+/// Stand-ins for parameters, so we can check every line parses before we need it.
+const SAMPLE_PARAMS: &[(&str, &str)] = &[("index", "0"), ("code", "ABC123")];
+
+/// How long `wait_for` waits if it isn't told.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL: Duration = Duration::from_millis(500);
+
+/// One line of a macro.
+#[derive(Debug, PartialEq)]
+enum Step {
+    /// `press Ctrl+Left` or `press Down x3`
+    Press(Key, u32),
+    /// `click 380 62`, where 1080p screenshot coordinates would be
+    Click(i32, i32),
+    /// `wait 2s` or `wait 200ms`
+    Wait(Duration),
+    /// `wait_for replay_list,unknown 5s` until a screenshot shows one of those
+    WaitFor(Vec<Screen>, Duration),
+    /// `type ABC123`
+    Type(String),
+}
+
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let bad = || format!("{} isn't a time like 2s or 200ms", text);
+    if let Some(ms) = text.strip_suffix("ms") {
+        ms.parse().map(Duration::from_millis).map_err(|_| bad())
+    } else if let Some(s) = text.strip_suffix('s') {
+        s.parse().map(Duration::from_secs).map_err(|_| bad())
+    } else {
+        Err(bad())
+    }
+}
+
+fn parse_screen(name: &str) -> Result<Screen, String> {
+    match name {
+        "replay_list" => Ok(Screen::ReplayList),
+        "error_dialog" => Ok(Screen::ErrorDialog),
+        "replay" => Ok(Screen::Replay),
+        "unknown" => Ok(Screen::Unknown),
+        _ => Err(format!(
+            "{} isn't replay_list, error_dialog, replay, or unknown",
+            name
+        )),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("{} isn't a whole number", text))
+}
+
+impl Step {
+    fn parse(line: &str) -> Result<Step, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let step = match words.as_slice() {
+            ["press", chord] => Step::Press(Key::parse(chord)?, 1),
+            ["press", chord, count] => {
+                let count = count
+                    .strip_prefix('x')
+                    .ok_or_else(|| format!("{} should be a count like x4", count))?;
+                Step::Press(Key::parse(chord)?, parse_number(count)?)
+            }
+            ["click", x, y] => Step::Click(parse_number(x)?, parse_number(y)?),
+            ["wait", time] => Step::Wait(parse_duration(time)?),
+            ["wait_for", screens] | ["wait_for", screens, _] => {
+                let screens = screens
+                    .split(',')
+                    .map(parse_screen)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let timeout = match words.get(2) {
+                    Some(x) => parse_duration(x)?,
+                    None => DEFAULT_TIMEOUT,
+                };
+                Step::WaitFor(screens, timeout)
+            }
+            ["type", text] => Step::Type(text.to_string()),
+            _ => return Err(format!("don't know how to \"{}\"", line)),
+        };
+        Ok(step)
+    }
+
+    fn run<G: Game>(&self, game: &mut G) -> Result<(), String> {
+        match self {
+            Step::Press(key, count) => {
+                for _ in 0..*count {
+                    game.send(key);
+                }
+            }
+            Step::Click(x, y) => game.click(*x, *y),
+            Step::Wait(time) => game.wait(*time),
+            Step::WaitFor(screens, timeout) => {
+                // counting the waits rather than the clock, so a simulated game doesn't take real time
+                let mut waited = Duration::from_secs(0);
+                loop {
+                    let screen = game.look();
+                    if screens.contains(&screen) {
+                        break;
+                    }
+                    if waited >= *timeout {
+                        return Err(format!(
+                            "waited {}s for {:?} but saw {:?}",
+                            timeout.as_secs(),
+                            screens,
+                            screen
+                        ));
+                    }
+                    game.wait(POLL);
+                    waited += POLL;
+                }
+            }
+            Step::Type(text) => game.type_text(text),
+        }
+        Ok(())
+    }
+}
+
+fn fill(line: &str, params: &[(&str, &str)]) -> String {
+    params.iter().fold(line.to_string(), |line, (name, value)| {
+        line.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+/// Named sequences of key presses, clicks, and waits for getting around the menus,
+/// so they can be fixed in "macros.txt" when the game's UI changes.
+pub struct Macros(HashMap<String, Vec<String>>);
+
+impl Macros {
+    const FILE: &'static str = "macros.txt";
+
+    fn parse(text: &str) -> Result<Macros, String> {
+        let mut result: HashMap<String, Vec<String>> = HashMap::new();
+        let mut current: Option<String> = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+                result.insert(name.to_string(), vec![]);
+                current = Some(name.to_string());
+                continue;
+            }
+            let name = current
+                .as_ref()
+                .ok_or_else(|| format!("line {} isn't in a [macro]", number + 1))?;
+            Step::parse(&fill(line, SAMPLE_PARAMS))
+                .map_err(|e| format!("line {}: {}", number + 1, e))?;
+            result
+                .get_mut(name)
+                .expect("Current macro went missing")
+                .push(line.to_string());
+        }
+        Ok(Macros(result))
+    }
+
+    pub fn builtin() -> Macros {
+        Macros::parse(BUILTIN).expect("Built-in macros don't parse")
+    }
+
+    /// The built-in macros, with any from "macros.txt" in their place.
+    pub fn load() -> Macros {
+        let mut result = Macros::builtin();
+        let text = match read_to_string(Macros::FILE) {
+            Ok(x) => x,
+            Err(_) => return result,
+        };
+        let overrides = Macros::parse(&text)
+            .unwrap_or_else(|e| panic!("Couldn't parse {}: {}", Macros::FILE, e));
+        for (name, lines) in overrides.0 {
+            if !result.0.contains_key(&name) {
+                panic!(
+                    "{} has a macro called {}, which isn't one we use",
+                    Macros::FILE,
+                    name
+                );
+            }
+            result.0.insert(name, lines);
+        }
+        result
+    }
+
+    /// Runs a macro, filling in `{name}` with each parameter's value.
+    pub fn run<G: Game>(
+        &self,
+        game: &mut G,
+        name: &str,
+        params: &[(&str, &str)],
+    ) -> Result<(), String> {
+        let lines = self
+            .0
+            .get(name)
+            .unwrap_or_else(|| panic!("No macro called {}", name));
+        for line in lines {
+            let step =
+                Step::parse(&fill(line, params)).map_err(|e| format!("macro {}: {}", name, e))?;
+            step.run(game)
+                .map_err(|e| format!("macro {}: {}", name, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        keys: Vec<Key>,
+        clicks: Vec<(i32, i32)>,
+        waited: Duration,
+        screens: Vec<Screen>,
+    }
+
+    impl Game for Recorder {
+        fn send(&mut self, key: &Key) {
+            self.keys.push(key.clone());
+        }
+
+        fn click(&mut self, x: i32, y: i32) {
+            self.clicks.push((x, y));
+        }
+
+        fn wait(&mut self, how_long: Duration) {
+            self.waited += how_long;
+        }
+
+        fn look(&mut self) -> Screen {
+            self.screens.pop().unwrap_or(Screen::Unknown)
+        }
+
+        fn start_recording(&mut self) {}
+
+        fn stop_recording(&mut self) {}
+    }
+
+    #[test]
+    fn parses_every_kind_of_step() {
+        assert_eq!(
+            Step::parse("press Ctrl+Left"),
+            Ok(Step::Press(ctrl(Left), 1))
+        );
+        assert_eq!(Step::parse("press Up x4"), Ok(Step::Press(Up, 4)));
+        assert_eq!(Step::parse("click 380 62"), Ok(Step::Click(380, 62)));
+        assert_eq!(
+            Step::parse("wait 200ms"),
+            Ok(Step::Wait(Duration::from_millis(200)))
+        );
+        assert_eq!(
+            Step::parse("wait_for replay_list,unknown 5s"),
+            Ok(Step::WaitFor(
+                vec![Screen::ReplayList, Screen::Unknown],
+                Duration::from_secs(5)
+            ))
+        );
+        assert_eq!(
+            Step::parse("type ABC123"),
+            Ok(Step::Type("ABC123".to_string()))
+        );
+        assert!(Step::parse("press Up 4").is_err());
+        assert!(Step::parse("wait 2").is_err());
+        assert!(Step::parse("jump").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_lines_with_their_number() {
+        let result = Macros::parse("[exit_replay]\npress Escape\nclick here 62\n");
+        assert_eq!(
+            result.err(),
+            Some("line 3: here isn't a whole number".to_string())
+        );
+        assert!(Macros::parse("press Escape").is_err());
+    }
+
+    #[test]
+    fn fills_in_parameters() {
+        let mut game = Recorder::default();
+        Macros::builtin()
+            .run(&mut game, "open_replay", &[("index", "2")])
+            .unwrap();
+        assert_eq!(game.keys, vec![Down, Down, Tab, Space]);
+        assert_eq!(game.waited, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn wait_for_gives_up() {
+        let macros = Macros::parse("[check]\nwait_for replay 2s\n").unwrap();
+        let mut game = Recorder::default();
+        assert!(macros.run(&mut game, "check", &[]).is_err());
+        let mut game = Recorder {
+            screens: vec![Screen::Replay, Screen::Unknown],
+            ..Default::default()
+        };
+        assert!(macros.run(&mut game, "check", &[]).is_ok());
+    }
+}
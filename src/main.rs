@@ -2,75 +2,174 @@
 extern crate serde_json;
 #[macro_use]
 extern crate lazy_static;
+#[macro_use]
+extern crate log;
 
+mod color;
+mod config;
+mod coords;
+mod corpus;
+mod debug_frames;
+mod detectors;
 mod image;
+mod keybindings;
+mod kill_feed;
+mod logging;
+mod macros;
+mod navigation;
 mod obs;
+mod probe;
+mod seek;
+mod slots;
 mod window;
 
-use crate::image::{InReplay, Screenshot};
+use crate::config::{Config, SideFallback};
+use crate::debug_frames::FrameDump;
+use crate::detectors::{
+    registry, GameoverDetector, GameoverSignals, Mark, RoundCounter, GAMEOVER_FRAMES,
+};
+use crate::image::{badge_outline, Badge, ClockDigits, Identity, InReplay, Screenshot};
+use crate::keybindings::Action;
+use crate::kill_feed::{Kill, KillFeed, KillLog};
+use crate::macros::Macros;
+use navigation::*;
 use obs::*;
+use probe::*;
+use seek::*;
+use slots::*;
 use std::env::{current_exe, set_current_dir};
-use std::ffi::OsString;
-use std::fs::read_dir;
-use std::io::{stdin, Write};
-use std::path::PathBuf;
+use std::ffi::{OsStr, OsString};
+use std::fs::{read_dir, write};
+use std::io::stdin;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use window::*;
 
+/// How alike two badges have to be to count as the same player.
+const SAME_PLAYER: f32 = 0.98;
+/// How unlike the expected badge the one on screen has to be before we say the camera drifted.
+/// This is looser than `SAME_PLAYER`, since the game is moving behind the badge while we record.
+const DRIFTED: f32 = 0.8;
+/// How long a player's badge can be missing before we decide they left the match.
+const LEAVER_TIMEOUT: Duration = Duration::from_secs(30);
+const PERSPECTIVES_FILE: &str = "perspectives.json";
+const KILLS_FILE: &str = "kills.json";
+
 lazy_static! {
     static ref RUNNING: Arc<AtomicBool> = { Arc::new(AtomicBool::new(true)) };
 }
 
+/// Set by `--dry-run`, where every keypress, click, OBS request, and ffmpeg command is printed instead of done.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::SeqCst)
+}
+
 fn main() {
-    println!(
-        r#"Thanks for using OWReplayRenderer, brought to you by boringcactus.
-Before we get started, make sure everything's all ready to go:
-- OBS and Overwatch are both running
-- OBS has `obs-websocket` installed and running on port 4444 with no authentication
-- Overwatch has all the default keybinds for the replay viewer: F1-F12 for player focus, Ctrl+P for pause, N to show/hide controls
-- Overwatch has Ctrl+Left bound to 'Jump to Start' and Ctrl+Right bound to 'Replay Forward'
-- Load up a replay, spectate yourself with one of F1-F12, take a 1080p screenshot of the whole screen, and save it next to OWReplayRenderer.exe as "username_badge.png"
-Got all that? Press Enter to continue."#
-    );
-    let _ = read_line();
+    logging::init();
+    let args = std::env::args().collect::<Vec<_>>();
+    // for working on the detectors: `cargo run -- --label shot.png ...` from the repo adds to the test corpus
+    if let Some(i) = args.iter().position(|x| x == "--label") {
+        corpus::label_frames(&args[i + 1..]);
+        return;
+    }
+    // and `cargo run --release -- --benchmark shot.png ...` to see which detectors are slow
+    if let Some(i) = args.iter().position(|x| x == "--benchmark") {
+        detectors::benchmark(&args[i + 1..]);
+        return;
+    }
+    // checking keys only needs a replay to try them in, not badges or anything else
+    let checking_keys = args.iter().any(|x| x == "--check-keys");
+    if args.iter().any(|x| x == "--dry-run") {
+        DRY_RUN.store(true, Ordering::SeqCst);
+        println!(
+            r#"Dry run: nothing will be pressed, clicked, recorded, or muxed, only printed.
+Screenshots come from the 1080p PNGs in the "dry_run_frames" folder next to OWReplayRenderer.exe, in name order.
+"#
+        );
+    }
+    if !checking_keys {
+        print_intro();
+    }
 
-    if !Screenshot::<InReplay>::has_me() {
+    if Identity::load_all().is_empty() {
         // if we didn't find it in the existing working directory, find it adjacent to the executable
         if let Ok(x) = current_exe() {
             if let Some(x) = x.parent() {
                 match set_current_dir(x) {
                     Ok(_) => (),
-                    Err(x) => eprintln!("Error looking for screenshot: {}", x),
+                    Err(x) => warn!("Error looking for screenshot: {}", x),
                 }
             }
         }
     }
 
-    while !Screenshot::<InReplay>::has_me() {
+    let config = Config::load();
+    logging::set_level(config.log_level);
+    detectors::install(&config.detectors);
+    color::install(config.palette.clone());
+
+    if checking_keys {
+        println!("Open any replay in Overwatch, then leave it alone until this is done.");
+        let mut obs = OBSClient::new();
+        let overwatch =
+            Window::find(&config.window, config.window_timeout).unwrap_or_else(|e| panic!("{}", e));
+        let ok = check_keys(&mut obs, &overwatch, &config);
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    let mut identities = Identity::load_all();
+    while identities.is_empty() {
         println!(
             r#"Couldn't find a screenshot with your username.
 Load up a replay, spectate yourself with one of F1-F12, take a 1080p screenshot of the whole screen, and save it next to OWReplayRenderer.exe as "username_badge.png".
 Press Enter when you've done that."#
         );
         let _ = read_line();
+        identities = Identity::load_all();
     }
+    println!(
+        "Tracking {}.",
+        identities
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
 
-    let replays = read_replay_range();
+    let replays = read_replays();
+    let selection = read_selection(config.team_size);
+    let range = read_time_range();
+    let clock = ClockDigits::load();
+    if (range.start > Duration::ZERO || range.rounds.is_some()) && clock.is_none() {
+        println!(
+            "Couldn't find clock_digits, so skipping ahead will count Replay Forward presses instead of reading the clock."
+        );
+    }
+    let session = Session {
+        config,
+        identities,
+        selection,
+        range,
+        clock,
+    };
 
     println!("Go make sure Overwatch is at the main menu, then come back here and press Enter.");
     let _ = read_line();
 
     println!(
-        r"That's all we need! You'll need to re-focus Overwatch yourself, so this tool can send it keyboard shortcuts.
-It'll render each entire game from the perspective of each player on your team, which will take a while.
+        r"That's all we need! It'll bring Overwatch to the front itself, so it can send it keyboard shortcuts.
+It'll render each entire game from the perspective of each player you picked, which will take a while.
 It'll record the oldest replay first and work its way forward.
 You can't do anything else with your computer during that time, either, unfortunately.
+If you do switch away, it'll pause (recording included) until you switch back.
 Once everything is rendered, it'll exit the replay viewer automatically, and stitch those videos together for easier viewing.
-Alt-tab back into Overwatch and then come back in a long time."
+Come back in a long time."
     );
 
     {
@@ -83,305 +182,1250 @@ Alt-tab back into Overwatch and then come back in a long time."
     }
 
     let replay_count = replays.len();
-    for (i, index) in replays.into_iter().rev().enumerate() {
+    let mut report = SessionReport::new();
+    let overwatch = Window::find(&session.config.window, session.config.window_timeout)
+        .unwrap_or_else(|e| panic!("{}", e));
+    let mut navigator = Navigator::new(Macros::load());
+    for (i, replay) in replays.iter().enumerate() {
         let mut obs = OBSClient::new();
         let record_dir = obs.use_subdir();
+        logging::log_to(&record_dir);
+        debug!(
+            "replay={} number={}/{}",
+            replay.to_json(),
+            i + 1,
+            replay_count
+        );
+        let mut game = Live {
+            window: &overwatch,
+            obs: &mut obs,
+        };
 
-        record(&mut obs, index, &record_dir);
+        let outcome = match replay {
+            ReplaySource::Highlight(index) => {
+                record_highlight(&mut game, &mut navigator, *index, &record_dir)
+            }
+            _ => record(&mut game, &mut navigator, replay, &session, &record_dir),
+        };
         if !RUNNING.load(Ordering::SeqCst) {
             return;
         }
-        mux(record_dir);
+        report.add(replay, &record_dir, &outcome);
+        // whatever happened, make sure we're somewhere we know before the next one
+        if let Err(e) = navigator.recover(&mut game) {
+            error!("Lost track of where we are in Overwatch ({}), stopping.", e);
+            report.print_skipped();
+            return;
+        }
+        if let Outcome::Skipped(reason) = outcome {
+            warn!("Skipped game {}/{}: {}", i + 1, replay_count, reason);
+            continue;
+        }
+        match replay {
+            ReplaySource::Highlight(_) => mux_single(record_dir),
+            _ => mux(record_dir, session.config.team_size),
+        }
         if !RUNNING.load(Ordering::SeqCst) {
             return;
         }
 
-        println!("Finished recording game {}/{}", i + 1, replay_count);
+        info!("Finished recording game {}/{}", i + 1, replay_count);
     }
 
+    report.print_skipped();
     println!("Done with everything! Press Enter to exit.");
     let _ = read_line();
 }
 
-fn read_replay_range() -> Vec<u8> {
+/// Everything that has to be set up before we start, and what can be changed.
+fn print_intro() {
+    println!(
+        r#"Thanks for using OWReplayRenderer, brought to you by boringcactus.
+Before we get started, make sure everything's all ready to go:
+- OBS and Overwatch are both running
+- OBS has `obs-websocket` installed and running on port 4444 with no authentication
+- Overwatch's replay viewer keybinds match the "keys" in "config.json", or the defaults if it has none:
+  F1-F12 for player focus, Ctrl+P for pause, N to show/hide controls, Ctrl+Left for 'Jump to Start', and Ctrl+Right for 'Replay Forward'
+  (run OWReplayRenderer.exe --check-keys to make sure they all work)
+- Optionally, run OWReplayRenderer.exe --dry-run to see everything it would do without touching Overwatch, OBS, or ffmpeg
+- Optionally, settings can be changed in "config.json" next to OWReplayRenderer.exe
+  (including "team_size": 5 for Overwatch 2's 5v5, since it's 6v6 otherwise,
+  and "log_level", for how much goes in the log.txt in each recording's folder,
+  and "debug_frames": true, to save marked-up screenshots of every side guess and game-over call there too, with "debug_font" if there's no C:\Windows\Fonts\arial.ttf,
+  and "detectors", to adjust a detector's thresholds, like {{"paused": {{"max_distance": 8}}}},
+  and "colorblind": "protanopia", "deuteranopia", or "tritanopia" if Overwatch has a colorblind filter on)
+- Optionally, if Overwatch's menus have moved around, the way this tool gets through them can be changed in "macros.txt"
+- Optionally, to log every kill to "kills.json", cut hero portraits, ability icons, and the headshot marker out of the kill feed in 1080p screenshots and save them in a "kill_feed_icons" folder as "ana.png", "ability_sleep_dart.png", "headshot.png", and so on
+- Optionally, to skip ahead by reading the replay clock, cut each digit 0-9 out of a 1080p screenshot of the replay controls and save them in a "clock_digits" folder as "0.png" to "9.png"
+- Load up a replay, spectate yourself with one of F1-F12, take a 1080p screenshot of the whole screen, and save it next to OWReplayRenderer.exe as "username_badge.png"
+  (if your team shares this PC, do the same for each teammate's account as "theirname_badge.png")
+Got all that? Press Enter to continue."#
+    );
+    let _ = read_line();
+}
+
+/// A replay to record: either a row in the Replays tab, a share code to import,
+/// or a row in the Highlights tab.
+#[derive(Clone, Debug)]
+enum ReplaySource {
+    Index(u8),
+    Code(String),
+    Highlight(u8),
+}
+
+impl ReplaySource {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            ReplaySource::Index(x) => json!(x),
+            ReplaySource::Code(x) => json!(x),
+            ReplaySource::Highlight(x) => json!(format!("h{}", x)),
+        }
+    }
+}
+
+/// Replay codes to record in one batch, one per line.
+const CODE_QUEUE_FILE: &str = "codes.txt";
+
+/// Replay codes are six letters and numbers, like "A1B2C3".
+fn parse_code(text: &str) -> Option<String> {
+    if text.len() == 6 && text.chars().all(|x| x.is_ascii_alphanumeric()) {
+        Some(text.to_ascii_uppercase())
+    } else {
+        None
+    }
+}
+
+fn read_code_queue() -> Result<Vec<String>, String> {
+    let queue = std::fs::read_to_string(CODE_QUEUE_FILE)
+        .map_err(|e| format!("couldn't read {}: {}", CODE_QUEUE_FILE, e))?;
+    queue
+        .lines()
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .map(|x| parse_code(x).ok_or_else(|| format!("{} is not a replay code", x)))
+        .collect()
+}
+
+/// Parses "4" or "1-4", where the numbers are from 1 to `max`.
+fn parse_range(piece: &str, max: u8) -> Result<(u8, u8), String> {
+    let range: Vec<&str> = piece.splitn(2, "-").collect();
+    let bounds = match range.as_slice() {
+        [n] => n.parse::<u8>().map(|x| (x, x)),
+        [a, b] => a
+            .parse::<u8>()
+            .and_then(|a| b.parse::<u8>().map(|b| (a, b))),
+        _ => unreachable!(),
+    };
+    let (lo, hi) = bounds.map_err(|e| e.to_string())?;
+    if lo > hi {
+        return Err(format!("{}-{} is not valid", lo, hi));
+    }
+    if lo == 0 || lo > max {
+        return Err(format!("{} is not valid", lo));
+    }
+    if hi > max {
+        return Err(format!("{} is not valid", hi));
+    }
+    Ok((lo, hi))
+}
+
+/// The most replays the Replays tab keeps.
+const MAX_REPLAYS: u8 = 10;
+/// The most highlights the Highlights tab keeps.
+const MAX_HIGHLIGHTS: u8 = 36;
+
+fn read_replays() -> Vec<ReplaySource> {
     println!(
-        r#"This tool can record whichever replays you want. Enter a range or set of ranges (e.g. "1-4, 6-7, 9"):"#
+        r#"This tool can record whichever replays you want. Enter a range or set of ranges (e.g. "1-4, 6-7, 9"),
+replay codes (e.g. "A1B2C3"), "queue" to record every code in {}, or highlights with an "h" (e.g. "h1-3"):"#,
+        CODE_QUEUE_FILE
     );
     let line = read_line();
     let pieces = line.split(',').map(|x| x.trim());
     let mut result = vec![];
+    let mut codes = vec![];
+    let mut highlights = vec![];
     for piece in pieces {
-        let range: Vec<&str> = piece.splitn(2, "-").collect();
-        let bounds = match range.as_slice() {
-            [n] => n.parse::<u8>().map(|x| (x, x)),
-            [a, b] => a
-                .parse::<u8>()
-                .and_then(|a| b.parse::<u8>().map(|b| (a, b))),
-            _ => unreachable!(),
-        };
-        let (lo, hi) = match bounds {
-            Ok((lo, hi)) => {
-                if lo > hi {
-                    println!("Bad range: {}-{} is not valid", lo, hi);
-                    return read_replay_range();
+        if piece.eq_ignore_ascii_case("queue") {
+            match read_code_queue() {
+                Ok(x) => codes.extend(x),
+                Err(e) => {
+                    println!("Bad queue: {}", e);
+                    return read_replays();
                 }
-                if lo == 0 || lo > 10 {
-                    println!("Bad range: {} is not valid", lo);
-                    return read_replay_range();
-                }
-                if hi > 10 {
-                    println!("Bad range: {} is not valid", hi);
-                    return read_replay_range();
+            }
+            continue;
+        }
+        if let Some(code) = parse_code(piece) {
+            codes.push(code);
+            continue;
+        }
+        if let Some(piece) = piece.strip_prefix('h').or_else(|| piece.strip_prefix('H')) {
+            match parse_range(piece, MAX_HIGHLIGHTS) {
+                Ok((lo, hi)) => highlights.extend(lo..=hi),
+                Err(e) => {
+                    println!("Bad highlight range: {}", e);
+                    return read_replays();
                 }
-                (lo, hi)
             }
+            continue;
+        }
+        match parse_range(piece, MAX_REPLAYS) {
+            Ok((lo, hi)) => result.extend(lo..=hi),
             Err(e) => {
                 println!("Bad range: {}", e);
-                return read_replay_range();
+                return read_replays();
             }
-        };
-        result.extend(lo..=hi);
+        }
     }
     result.sort();
+    highlights.sort();
+    highlights.dedup();
+    // oldest first, and all before any codes, since importing a code could shift the list
     result
+        .into_iter()
+        .rev()
+        .map(ReplaySource::Index)
+        .chain(codes.into_iter().map(ReplaySource::Code))
+        .chain(highlights.into_iter().map(ReplaySource::Highlight))
+        .collect()
 }
 
-#[derive(Copy, Clone)]
-enum Side {
-    Red,
-    Blue,
+fn read_selection(team_size: u8) -> Selection {
+    println!(
+        r#"Whose perspectives do you want to record? Enter "team" (everyone on your team), "me" (just the tracked badges), "blue", "red", "all", or F-keys (e.g. "F1, F3, F9").
+Leave it blank to record your team:"#
+    );
+    match Selection::parse(&read_line(), team_size) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Bad selection: {}", e);
+            read_selection(team_size)
+        }
+    }
 }
 
-impl Into<Vec<Key>> for Side {
-    fn into(self) -> Vec<Key> {
-        match self {
-            Side::Blue => return vec![F1, F2, F3, F4, F5, F6],
-            Side::Red => return vec![F7, F8, F9, F10, F11, F12],
+fn read_time_range() -> TimeRange {
+    println!(
+        r#"Enter the part of each game to record (e.g. "4:30-9:00", "4:30-", "-9:00", "round 2", or "rounds 2-3"), or leave it blank to record the whole thing:"#
+    );
+    match TimeRange::parse(&read_line()) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Bad time range: {}", e);
+            read_time_range()
         }
     }
 }
 
-fn guess_side(obs: &mut OBSClient, overwatch: &Window) -> Side {
-    // skip forward a bit
-    big_sleep();
-    overwatch.send(&ctrl(Right));
-    big_sleep();
-    overwatch.send(&ctrl(Right));
-    big_sleep();
-    overwatch.send(&ctrl(Right));
-    big_sleep();
+/// Where a tracked identity was spotted while guessing the side.
+struct Sighting<'a> {
+    identity: &'a Identity,
+    side: Side,
+    slot: Key,
+    score: f32,
+}
+
+/// How well every identity matched while spectating one slot.
+struct SlotScores {
+    side: Side,
+    slot: Key,
+    /// Whether there was actually a player in this slot to spectate.
+    occupied: bool,
+    /// Who we saw, so we can tell if the camera drifts off them later.
+    badge: Badge,
+    scores: Vec<f32>,
+}
+
+struct SideGuess<'a> {
+    /// `None` if nobody matched confidently enough, even after retrying.
+    side: Option<Side>,
+    sightings: Vec<Sighting<'a>>,
+    slots: Vec<SlotScores>,
+}
 
-    // see if we can find the player
-    let best = [Side::Blue, Side::Red].iter().map(|&side| {
-        let keys: Vec<Key> = side.into();
-        let max = keys.iter().map(|key| {
-            overwatch.send(key);
+fn score_slots(
+    obs: &mut OBSClient,
+    overwatch: &Window,
+    config: &Config,
+    identities: &[Identity],
+    frames: Option<&FrameDump>,
+) -> Vec<SlotScores> {
+    let mut result: Vec<SlotScores> = vec![];
+    for &side in [Side::Blue, Side::Red].iter() {
+        for slot in side.players(config.team_size) {
+            overwatch.send(&config.keys.spectate(&slot));
             big_sleep();
-            obs.get_screenshot::<InReplay>().is_me_score()
-        }).max_by(|n1, n2| n1.partial_cmp(n2).expect("Couldn't compare floats")).expect("Couldn't compare floats");
-        (side, max)
-    }).max_by(|(_, n1), (_, n2)| n1.partial_cmp(n2).expect("Couldn't compare floats"));
-    best.expect("Couldn't compare floats").0
+            let screenshot = obs.get_screenshot::<InReplay>();
+            let scores = screenshot.identity_scores(identities);
+            let badge = screenshot.badge();
+            // if the camera didn't go anywhere new, there's nobody in this slot
+            let occupied = screenshot.has_player()
+                && result
+                    .iter()
+                    .filter(|x| x.occupied)
+                    .all(|x| badge.same_player_score(&x.badge) < SAME_PLAYER);
+            if let Some(frames) = frames {
+                let mark = Mark {
+                    outline: badge_outline(),
+                    label: format_scores(identities, &scores),
+                    found: occupied,
+                };
+                let state = if occupied { "occupied" } else { "empty" };
+                frames.save(
+                    &format!("side_{:?}", slot),
+                    &screenshot,
+                    &[mark],
+                    &format!("{:?} ({:?}): {}", slot, side, state),
+                );
+            }
+            result.push(SlotScores {
+                side,
+                slot,
+                occupied,
+                badge,
+                scores,
+            });
+        }
+    }
+    result
 }
 
-fn record(obs: &mut OBSClient, index: u8, record_dir: &PathBuf) {
-    let overwatch = Window::overwatch();
+fn format_scores(identities: &[Identity], scores: &[f32]) -> String {
+    identities
+        .iter()
+        .zip(scores.iter())
+        .map(|(identity, score)| format!("{} {:.2}", identity.name, score))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
-    overwatch.await_focus();
+fn print_slot_scores(slots: &[SlotScores], identities: &[Identity]) {
+    for slot in slots {
+        let scores = format_scores(identities, &slot.scores);
+        if slot.occupied {
+            info!("  {:?} ({:?}): {}", slot.slot, slot.side, scores);
+        } else {
+            info!("  {:?} ({:?}): empty", slot.slot, slot.side);
+        }
+    }
+}
 
-    // open the replays tab
-    small_sleep();
-    overwatch.send(&Up);
-    overwatch.send(&Up);
-    overwatch.send(&Up);
-    overwatch.send(&Up);
-    overwatch.send(&Space);
-    big_sleep();
-    overwatch.click(380, 62);
-    big_sleep();
-    if !RUNNING.load(Ordering::SeqCst) {
-        return;
+fn guess_side<'a>(
+    obs: &mut OBSClient,
+    overwatch: &Window,
+    config: &Config,
+    identities: &'a [Identity],
+    frames: Option<&FrameDump>,
+) -> SideGuess<'a> {
+    let mut attempt = 0;
+    loop {
+        // skip forward a bit (and a bit further each time we retry)
+        let forward = config.keys.get(Action::ReplayForward);
+        big_sleep();
+        overwatch.send(&forward);
+        big_sleep();
+        overwatch.send(&forward);
+        big_sleep();
+        overwatch.send(&forward);
+        big_sleep();
+
+        let slots = score_slots(obs, overwatch, config, identities, frames);
+        info!("Badge scores:");
+        print_slot_scores(&slots, identities);
+
+        // each identity is wherever it matched best, if that's good enough
+        let sightings = identities
+            .iter()
+            .enumerate()
+            .filter_map(|(i, identity)| {
+                let best = slots.iter().filter(|x| x.occupied).max_by(|a, b| {
+                    a.scores[i]
+                        .partial_cmp(&b.scores[i])
+                        .expect("Couldn't compare floats")
+                })?;
+                Some(Sighting {
+                    identity,
+                    side: best.side,
+                    slot: best.slot.clone(),
+                    score: best.scores[i],
+                })
+            })
+            .filter(|x| x.score >= config.min_confidence)
+            .collect::<Vec<_>>();
+
+        // and we're on the side of whoever matched best overall
+        let side = sightings
+            .iter()
+            .max_by(|a, b| {
+                a.score
+                    .partial_cmp(&b.score)
+                    .expect("Couldn't compare floats")
+            })
+            .map(|x| x.side);
+        if side.is_some() || attempt >= config.side_retries || !RUNNING.load(Ordering::SeqCst) {
+            return SideGuess {
+                side,
+                sightings,
+                slots,
+            };
+        }
+
+        attempt += 1;
+        info!(
+            "Nobody matched better than {:.2}, looking again further into the replay ({}/{})...",
+            config.min_confidence, attempt, config.side_retries
+        );
+    }
+}
+
+/// Write down who was found where, so the recordings can be told apart later.
+fn write_sightings(record_dir: &Path, guess: &SideGuess, identities: &[Identity]) {
+    let found = guess
+        .sightings
+        .iter()
+        .map(|x| {
+            json!({
+                "name": x.identity.name,
+                "side": format!("{:?}", x.side),
+                "slot": format!("{:?}", x.slot),
+                "score": x.score,
+            })
+        })
+        .collect::<Vec<_>>();
+    let slots = guess
+        .slots
+        .iter()
+        .map(|x| {
+            let scores = identities
+                .iter()
+                .zip(x.scores.iter())
+                .map(|(identity, &score)| (identity.name.clone(), json!(score)))
+                .collect::<serde_json::Map<_, _>>();
+            json!({
+                "side": format!("{:?}", x.side),
+                "slot": format!("{:?}", x.slot),
+                "occupied": x.occupied,
+                "scores": scores,
+            })
+        })
+        .collect::<Vec<_>>();
+    let metadata = json!({
+        "side": guess.side.map(|x| format!("{:?}", x)),
+        "identities": found,
+        "slots": slots,
+    });
+    let metadata = serde_json::to_string_pretty(&metadata).expect("Couldn't serialize identities");
+    write(record_dir.join("identities.json"), metadata).expect("Couldn't write identities");
+}
+
+/// Ask which side to record, or `None` to skip the replay.
+fn ask_side(team_size: u8) -> Option<Vec<Key>> {
+    println!(
+        "Couldn't tell which side you were on. Record (b)lue, (r)ed, b(o)th, or (s)kip this replay?"
+    );
+    let answer = match read_line().to_lowercase().as_str() {
+        "b" | "blue" => Some(Side::Blue.players(team_size)),
+        "r" | "red" => Some(Side::Red.players(team_size)),
+        "o" | "both" => Some(both_sides(team_size)),
+        "s" | "skip" => None,
+        _ => return ask_side(team_size),
+    };
+    println!("Alt-tab back into Overwatch to keep going.");
+    answer
+}
+
+/// What happened to a replay we tried to record.
+enum Outcome {
+    Recorded,
+    Skipped(String),
+}
+
+/// What happened to every replay this session, saved next to the recordings as we go.
+struct SessionReport {
+    file_name: String,
+    entries: Vec<serde_json::Value>,
+}
+
+impl SessionReport {
+    fn new() -> SessionReport {
+        SessionReport {
+            file_name: format!("session {}.json", timestamp()),
+            entries: vec![],
+        }
     }
 
-    // open the replay
-    for _ in 0..index {
-        overwatch.send(&Down);
+    fn add(&mut self, replay: &ReplaySource, record_dir: &Path, outcome: &Outcome) {
+        let mut entry = json!({
+            "replay": replay.to_json(),
+            "directory": record_dir.to_string_lossy(),
+        });
+        match outcome {
+            Outcome::Recorded => entry["status"] = json!("recorded"),
+            Outcome::Skipped(reason) => {
+                entry["status"] = json!("skipped");
+                entry["reason"] = json!(reason);
+            }
+        }
+        self.entries.push(entry);
+
+        let path = record_dir
+            .parent()
+            .expect("No path parent")
+            .join(&self.file_name);
+        let report =
+            serde_json::to_string_pretty(&self.entries).expect("Couldn't serialize report");
+        write(path, report).expect("Couldn't write session report");
+    }
+
+    fn print_skipped(&self) {
+        for entry in self.entries.iter().filter(|x| x["status"] == "skipped") {
+            warn!("Skipped replay {}: {}", entry["replay"], entry["reason"]);
+        }
+    }
+}
+
+/// Everything that stays the same for every replay this session.
+struct Session {
+    config: Config,
+    identities: Vec<Identity>,
+    selection: Selection,
+    range: TimeRange,
+    /// Only if the user gave us the digits to read the clock with.
+    clock: Option<ClockDigits>,
+}
+
+fn record(
+    game: &mut Live,
+    navigator: &mut Navigator,
+    replay: &ReplaySource,
+    session: &Session,
+    record_dir: &PathBuf,
+) -> Outcome {
+    let config = &session.config;
+    let identities = &session.identities;
+    let selection = &session.selection;
+
+    game.window.focus();
+
+    // open the replays tab, unless we never left it
+    if let Err(reason) = navigator.open_tab(game, State::Replays) {
+        return Outcome::Skipped(reason);
     }
-    overwatch.send(&Tab);
-    overwatch.send(&Space);
     if !RUNNING.load(Ordering::SeqCst) {
-        return;
+        return Outcome::Recorded;
     }
 
-    // wait for it to load
-    sleep(Duration::from_secs(10));
+    // open the replay, and make sure it actually did
+    if let Err(reason) = navigator.open_replay(game, replay) {
+        return Outcome::Skipped(reason);
+    }
     if !RUNNING.load(Ordering::SeqCst) {
-        return;
+        return Outcome::Recorded;
     }
 
     // pause it
-    overwatch.send(&ctrl(P));
+    game.send(&config.keys.get(Action::Pause));
 
     // guess the side
-    let side = guess_side(obs, &overwatch);
-    let side: Vec<Key> = side.into();
+    let frames = FrameDump::new(record_dir, config);
+    let guess = guess_side(game.obs, game.window, config, identities, frames.as_ref());
+    write_sightings(record_dir, &guess, identities);
     if !RUNNING.load(Ordering::SeqCst) {
-        return;
+        return Outcome::Recorded;
     }
+    let found = guess
+        .sightings
+        .iter()
+        .map(|x| x.slot.clone())
+        .collect::<Vec<_>>();
+    let players: Vec<Key> = match selection.resolve(guess.side, &found, config.team_size) {
+        Some(x) => x,
+        None => {
+            // falling back to a whole side would record everyone but the one we asked for
+            let (fallback, reason) = if *selection == Selection::Me {
+                (None, "couldn't find any of our badges")
+            } else {
+                let fallback = match config.side_fallback {
+                    SideFallback::BothTeams => Some(both_sides(config.team_size)),
+                    SideFallback::Ask => ask_side(config.team_size),
+                    SideFallback::Skip => None,
+                };
+                (fallback, "couldn't tell which side we were on")
+            };
+            match fallback {
+                Some(x) => x,
+                None => {
+                    // if this doesn't work, recovering afterwards will try again
+                    if let Err(e) = navigator.exit_replay(game) {
+                        warn!("{}", e);
+                    }
+                    return Outcome::Skipped(reason.to_string());
+                }
+            }
+        }
+    };
 
-    for player in side {
-        record_once(player, obs, &overwatch, record_dir);
+    let mut perspectives = vec![];
+    // until we've seen the game end once, we can only guess how long it is
+    let mut replay_length: Option<Duration> = None;
+    // and until we've seen the rounds we want, we can only watch for them
+    let mut range = session.range.clone();
+    for player in players {
+        // name the recording after whoever we found in this slot, if anyone
+        let label = guess
+            .sightings
+            .iter()
+            .find(|x| x.slot == player)
+            .map(|x| x.identity.name.as_str());
+        let scanned = guess.slots.iter().find(|x| x.slot == player);
+        let perspective = match scanned {
+            Some(x) if !x.occupied => {
+                info!("{:?} empty.", player);
+                Perspective::Empty
+            }
+            _ => {
+                let expected = scanned.map(|x| &x.badge);
+                let watchdog = match replay_length {
+                    Some(x) => x + x / 10 + Duration::from_secs(30),
+                    None => config.max_game_length,
+                };
+                let target = Target {
+                    player: player.clone(),
+                    label,
+                    expected,
+                };
+                record_once(
+                    &target,
+                    watchdog,
+                    &range,
+                    session,
+                    game,
+                    navigator,
+                    record_dir,
+                    frames.as_ref(),
+                )
+            }
+        };
+        if let Perspective::Recorded(ref x) = perspective {
+            if !x.timed_out && !x.partial {
+                replay_length = replay_length.max(Some(x.length));
+            }
+            // once one perspective has seen where the rounds are, the rest can skip straight to them
+            if let (Some((first, last)), false) = (range.rounds, x.timed_out) {
+                if let Some(times) = TimeRange::for_rounds(first, last, &x.round_ends) {
+                    info!(
+                        "Rounds {}-{} are from {}s to {}.",
+                        first,
+                        last,
+                        times.start.as_secs(),
+                        times
+                            .end
+                            .map_or("the end".to_string(), |x| format!("{}s", x.as_secs()))
+                    );
+                    range = times;
+                }
+            }
+        }
+        perspectives.push((player, label, perspective));
         if !RUNNING.load(Ordering::SeqCst) {
-            return;
+            write_perspectives(record_dir, &perspectives);
+            return Outcome::Recorded;
         }
     }
+    write_perspectives(record_dir, &perspectives);
+    write_kills(record_dir, &perspectives);
 
-    // quit from this replay (click to dismiss the controls if they are shown)
-    big_sleep();
-    overwatch.click(1710, 1003);
-    overwatch.send(&Escape);
-    overwatch.send(&Up);
-    overwatch.send(&Up);
-    overwatch.send(&Space);
+    if let Err(e) = navigator.exit_replay(game) {
+        warn!("{}", e);
+    }
+
+    if perspectives
+        .iter()
+        .all(|(_, _, x)| *x == Perspective::Empty)
+    {
+        return Outcome::Skipped("nobody was in any of the slots we wanted".to_string());
+    }
+
+    info!("Finished recording everyone's perspective!");
+    Outcome::Recorded
+}
+
+/// How recording one slot went.
+#[derive(Debug, PartialEq)]
+enum Perspective {
+    Recorded(Recording),
+    /// Nobody was in the slot, so there's nothing to record.
+    Empty,
+    /// The player vanished partway through, so the recording stops there.
+    Left {
+        recording: Recording,
+        after: Duration,
+    },
+    /// Ctrl-C stopped it, with whatever got recorded before then.
+    Interrupted(Option<Recording>),
+}
+
+#[derive(Debug, PartialEq)]
+struct Recording {
+    files: Vec<String>,
+    /// How far into the replay the recording starts.
+    offset: Duration,
+    length: Duration,
+    /// Whether we gave up waiting for the game to end.
+    timed_out: bool,
+    /// Whether it only covers part of the game, because of the time range, so it's no guide to how long games are.
+    partial: bool,
+    /// When the camera wandered off to someone else and had to be sent back.
+    drifts: Vec<Duration>,
+    /// When Overwatch lost focus and the recording was paused, and for how long.
+    /// The replay is sent back to where it was once focus returns, so nothing is missing,
+    /// though up to a Replay Forward's worth may be recorded twice.
+    focus_lost: Vec<(Duration, Duration)>,
+    /// Everything in the kill feed, by how far into the replay it showed up,
+    /// counted from wherever the replay clock last said we were.
+    kills: Vec<(Duration, Kill)>,
+    /// How far into the replay each round ended, as far as we watched.
+    round_ends: Vec<Duration>,
+}
+
+impl Recording {
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "files": self.files,
+            "starts_at_seconds": self.offset.as_secs(),
+            "length_seconds": self.length.as_secs(),
+            "timed_out": self.timed_out,
+            "partial": self.partial,
+            "drifts_at_seconds": self.drifts.iter().map(|x| x.as_secs()).collect::<Vec<_>>(),
+            "focus_lost": self
+                .focus_lost
+                .iter()
+                .map(|(at, length)| json!({"at_seconds": at.as_secs(), "for_seconds": length.as_secs()}))
+                .collect::<Vec<_>>(),
+            "round_ends_at_seconds": self.round_ends.iter().map(|x| x.as_secs()).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Write down how each slot went, flagging the ones that were skipped or cut short.
+fn write_perspectives(record_dir: &Path, perspectives: &[(Key, Option<&str>, Perspective)]) {
+    let perspectives = perspectives
+        .iter()
+        .map(|(slot, label, perspective)| {
+            let mut result = match perspective {
+                Perspective::Recorded(recording) => {
+                    let mut result = recording.to_json();
+                    result["status"] = json!("recorded");
+                    result
+                }
+                Perspective::Empty => json!({ "status": "empty" }),
+                Perspective::Left { recording, after } => {
+                    let mut result = recording.to_json();
+                    result["status"] = json!("left");
+                    result["left_after_seconds"] = json!(after.as_secs());
+                    result
+                }
+                Perspective::Interrupted(recording) => {
+                    let mut result = recording.as_ref().map_or(json!({}), Recording::to_json);
+                    result["status"] = json!("interrupted");
+                    result
+                }
+            };
+            result["slot"] = json!(format!("{:?}", slot));
+            result["name"] = json!(label);
+            result
+        })
+        .collect::<Vec<_>>();
+    let metadata =
+        serde_json::to_string_pretty(&perspectives).expect("Couldn't serialize perspectives");
+    write(record_dir.join(PERSPECTIVES_FILE), metadata).expect("Couldn't write perspectives");
+}
+
+/// Write down the kill feed from whichever perspective read the most of it, since every perspective
+/// sees the same feed. Which team is which depends on who that was, so they're written down too.
+fn write_kills(record_dir: &Path, perspectives: &[(Key, Option<&str>, Perspective)]) {
+    let best = perspectives
+        .iter()
+        .filter_map(|(slot, _, perspective)| match perspective {
+            Perspective::Recorded(recording)
+            | Perspective::Left { recording, .. }
+            | Perspective::Interrupted(Some(recording)) => Some((slot, recording)),
+            Perspective::Empty | Perspective::Interrupted(None) => None,
+        })
+        .max_by_key(|(_, recording)| recording.kills.len());
+    let (slot, recording) = match best {
+        Some(x) if !x.1.kills.is_empty() => x,
+        _ => return,
+    };
+    let kills = recording
+        .kills
+        .iter()
+        .map(|(at, kill)| {
+            let mut result = serde_json::Value::from(kill.clone());
+            result["at_seconds"] = json!(at.as_secs());
+            result
+        })
+        .collect::<Vec<_>>();
+    let metadata = json!({
+        "spectating": format!("{:?}", slot),
+        "kills": kills,
+    });
+    let metadata = serde_json::to_string_pretty(&metadata).expect("Couldn't serialize kills");
+    write(record_dir.join(KILLS_FILE), metadata).expect("Couldn't write kills");
+}
+
+/// The recordings that were cut short because their player left, according to `write_perspectives`.
+fn truncated_recordings(record_dir: &Path) -> Vec<OsString> {
+    let perspectives = match std::fs::read_to_string(record_dir.join(PERSPECTIVES_FILE)) {
+        Ok(x) => x,
+        Err(_) => return vec![],
+    };
+    let perspectives: serde_json::Value =
+        serde_json::from_str(&perspectives).expect("Couldn't parse perspectives");
+    perspectives
+        .as_array()
+        .expect("Perspectives were not a list!")
+        .iter()
+        .filter(|x| x["status"] == "left")
+        .flat_map(|x| x["files"].as_array().cloned().unwrap_or_default())
+        .filter_map(|x| x.as_str().map(OsString::from))
+        .collect()
+}
+
+/// Highlights stop by themselves after about 12 seconds; this leaves some room for loading.
+const HIGHLIGHT_LENGTH: Duration = Duration::from_secs(20);
+
+/// Highlights don't have anyone to spectate, so we just play each one and record whatever's on screen.
+fn record_highlight(
+    game: &mut Live,
+    navigator: &mut Navigator,
+    index: u8,
+    record_dir: &PathBuf,
+) -> Outcome {
+    game.window.focus();
+
+    if let Err(reason) = navigator.open_tab(game, State::Highlights) {
+        return Outcome::Skipped(reason);
+    }
+    if !RUNNING.load(Ordering::SeqCst) {
+        return Outcome::Recorded;
+    }
+
+    if let Err(reason) = navigator.play_highlight(game, index) {
+        return Outcome::Skipped(reason);
+    }
+    sleep_for(HIGHLIGHT_LENGTH);
+    navigator.finish_highlight(game);
     big_sleep();
+    rename(record_dir, None);
 
-    println!("Finished recording everyone's perspective!")
+    info!("Finished recording highlight {}!", index);
+    Outcome::Recorded
 }
 
 fn read_line() -> String {
     let stdin = stdin();
     let mut result = String::new();
-    stdin.read_line(&mut result).expect("Couldn't read from stdin");
+    stdin
+        .read_line(&mut result)
+        .expect("Couldn't read from stdin");
     result.trim().to_string()
 }
 
-fn record_once(player: Key, obs: &mut OBSClient, overwatch: &Window, record_dir: &PathBuf) {
+/// Whether the camera has wandered off the player we meant to watch.
+/// Without their badge to go on, we can't tell, so we assume it has.
+fn has_drifted(screenshot: &Screenshot<InReplay>, expected: Option<&Badge>) -> bool {
+    match expected {
+        Some(expected) => {
+            screenshot.has_player() && screenshot.badge().same_player_score(expected) < DRIFTED
+        }
+        None => true,
+    }
+}
+
+/// Who one recording follows.
+struct Target<'a> {
+    player: Key,
+    /// Whoever we found in this slot, if anyone, to name the recording after.
+    label: Option<&'a str>,
+    /// Their badge from the slot scan, to notice if the camera drifts off them.
+    expected: Option<&'a Badge>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_once(
+    target: &Target,
+    watchdog: Duration,
+    range: &TimeRange,
+    session: &Session,
+    game: &mut Live,
+    navigator: &mut Navigator,
+    record_dir: &PathBuf,
+    frames: Option<&FrameDump>,
+) -> Perspective {
+    let player = target.player.clone();
+    let (label, expected) = (target.label, target.expected);
+    let keys = &session.config.keys;
+    let spectate = keys.spectate(&player);
     // make sure we don't start while overwatch is not focused
-    overwatch.await_focus();
+    game.window.await_focus();
     // tell overwatch to watch the designated player
-    overwatch.send(&player);
+    game.window.send(&spectate);
     // tell overwatch to skip to the beginning
-    overwatch.send(&ctrl(Left));
+    game.window.send(&keys.get(Action::JumpToStart));
     // give it a while to re-load
     big_sleep();
     big_sleep();
     if !RUNNING.load(Ordering::SeqCst) {
-        return;
+        return Perspective::Interrupted(None);
     }
     // dismiss the controls if they're shown
-    overwatch.click(1710, 1003);
+    game.window.click(1710, 1003);
     big_sleep();
     // show the controls
-    overwatch.send(&N);
+    game.window.send(&keys.get(Action::ToggleControls));
     big_sleep();
+    let shown = game.obs.get_screenshot::<InReplay>();
+    // the timeline is a known blue and grey, so it shows how brightness, gamma, and HDR are changing colors
+    if !shown.calibrate() {
+        warn!("couldn't calibrate colors off the timeline, so detectors use the uncalibrated ones");
+    }
     // if it's not definitely paused...
-    if !obs.get_screenshot::<InReplay>().is_definitely_paused() {
+    if !shown.is_definitely_paused() {
         // pause it
-        overwatch.send(&ctrl(P));
+        game.window.send(&keys.get(Action::Pause));
         // skip to the beginning again
-        overwatch.send(&ctrl(Left));
+        game.window.send(&keys.get(Action::JumpToStart));
     }
+    // skip ahead to where we want to start, while the clock is showing
+    let mut offset = if range.start > Duration::ZERO {
+        seek(
+            game.obs,
+            game.window,
+            range.start,
+            &session.config,
+            session.clock.as_ref(),
+        )
+    } else {
+        Duration::ZERO
+    };
     // dismiss the controls
-    overwatch.send(&N);
+    game.window.send(&keys.get(Action::ToggleControls));
     // chase the target
-    overwatch.send(&player);
+    game.window.send(&spectate);
     // tell OBS to start recording
-    obs.start_recording();
+    navigator.start_recording(game);
     // wait a bit so OBS can catch up
     big_sleep();
+    // if we're picking rounds we haven't found yet, hold the recording until the ones before them are over.
+    // OBS refuses to pause until it's actually recording, and the replay hasn't moved yet anyway
+    let mut waiting = range.rounds.map_or(false, |(first, _)| first > 1);
+    if waiting {
+        game.obs.pause_recording();
+    }
     // tell overwatch to unpause
-    overwatch.send(&ctrl(P));
+    game.window.send(&keys.get(Action::Pause));
+    let mut started = now();
+    let mut last_seen = started;
+    let mut left = false;
+    let mut interrupted = false;
+    let mut timed_out = false;
+    let mut partial = offset > Duration::ZERO || range.rounds.is_some();
+    let mut drifts = vec![];
+    let mut focus_lost = vec![];
+    // how long the recording was paused for while Overwatch didn't have focus
+    let mut away = Duration::ZERO;
+    // where the replay last was for sure, off its clock if we could read it, and how much was
+    // recorded by then. The clock's hidden while recording, so from there on it's counted
+    let mut known = (offset, Duration::ZERO);
+    let mut gameover = GameoverDetector::new();
+    let (mut last_signals, mut last_streak) = (GameoverSignals::default(), 0);
+    let mut rounds = RoundCounter::new();
+    let mut round_ends = vec![];
+    let mut kills = KillLog::default();
+    let mut feed_read: Option<Duration> = None;
     // while the game hasn't ended...
-    while !obs.get_screenshot::<InReplay>().is_gameover() {
-        // spam
-        overwatch.send(&player);
+    loop {
+        // if the user switched away, hold the recording until they're back,
+        // since there's no telling what's on screen in the meantime
+        if !game.window.has_focus() {
+            let at = now() - started - away;
+            let lost = now();
+            if !waiting {
+                game.obs.pause_recording();
+            }
+            game.window.await_focus();
+            // the replay kept playing without us, so go back to where the recording stopped
+            let to = known.0 + at - known.1;
+            known = (pause_and_seek(game, session, to), at);
+            game.window.send(&spectate);
+            if !waiting {
+                game.obs.resume_recording();
+            }
+            big_sleep();
+            game.window.send(&keys.get(Action::Pause));
+            away += now() - lost;
+            focus_lost.push((at, now() - lost));
+            last_seen = now();
+        }
+        // how much of the replay is recorded so far, and where in it we are
+        let recorded = now() - started - away;
+        let replay_at = known.0 + recorded - known.1;
+        let screenshot = game.obs.get_screenshot::<InReplay>();
+        let reading = screenshot.detect(&registry().gameover());
+        let signals = reading.value;
+        let over = gameover.update(&signals);
+        if registry().kill_feed.has_icons()
+            && feed_read.map_or(true, |x| replay_at >= x + KillFeed::EVERY)
+        {
+            feed_read = Some(replay_at);
+            let feed = screenshot.detect(&registry().kill_feed).value;
+            kills.update(replay_at, &feed);
+        }
+        // only when something changed, or a game that ends slowly would save thousands of the same frame
+        let changed = signals != last_signals || gameover.streak != last_streak;
+        last_signals = signals;
+        last_streak = gameover.streak;
+        if let (Some(frames), true) = (frames, changed) {
+            frames.save(
+                &format!("gameover_{:?}", player),
+                &screenshot,
+                &reading.marks,
+                &format!(
+                    "{:?} at {}s: streak {}/{}, confidence {:.2}",
+                    player,
+                    recorded.as_secs(),
+                    gameover.streak,
+                    GAMEOVER_FRAMES,
+                    reading.confidence
+                ),
+            );
+        }
+        if over {
+            break;
+        }
+        // (or at least the part we wanted hasn't)
+        if rounds.update(&signals) {
+            round_ends.push(replay_at);
+            let (first, last) = range.rounds.unwrap_or_default();
+            if range.rounds.is_some() && rounds.ended >= last {
+                break;
+            }
+            if waiting && rounds.ended + 1 == first {
+                // the first round we want starts now, and so does the recording
+                info!("Round {} starting at {}s.", first, replay_at.as_secs());
+                game.obs.resume_recording();
+                waiting = false;
+                offset = replay_at;
+                started = now();
+                away = Duration::ZERO;
+                known = (replay_at, Duration::ZERO);
+                drifts.clear();
+                focus_lost.clear();
+            }
+        }
+        if let Some(end) = range.end {
+            if replay_at >= end {
+                partial = true;
+                break;
+            }
+        }
+        // (or gone on far longer than it possibly could have)
+        if recorded > watchdog {
+            warn!(
+                "Recording {:?} has gone on for {}s without the game ending, stopping.",
+                player,
+                watchdog.as_secs()
+            );
+            // whatever was on screen then should have counted as the end, so it's worth a look
+            if let Some(frames) = frames {
+                frames.save(
+                    &format!("timed_out_{:?}", player),
+                    &screenshot,
+                    &reading.marks,
+                    &format!("{:?} timed out at {}s", player, watchdog.as_secs()),
+                );
+            }
+            timed_out = true;
+            break;
+        }
+        // and the player is still around...
+        if screenshot.has_player() {
+            last_seen = now();
+        } else if now() - last_seen > LEAVER_TIMEOUT {
+            left = true;
+            break;
+        }
+        // get back to the right player if we've lost them
+        if has_drifted(&screenshot, expected) {
+            if expected.is_some() {
+                let at = recorded;
+                warn!(
+                    "Camera drifted off {:?} after {}s, switching back.",
+                    player,
+                    at.as_secs()
+                );
+                drifts.push(at);
+            }
+            game.window.send(&spectate);
+        }
         med_sleep();
         if !RUNNING.load(Ordering::SeqCst) {
-            return;
+            interrupted = true;
+            break;
         }
     }
     // wait another while
     big_sleep();
     // stop recording
-    obs.stop_recording();
+    navigator.stop_recording(game);
     // wait a bit
     big_sleep();
     // jump to beginning again
-    overwatch.send(&ctrl(Left));
+    game.window.send(&keys.get(Action::JumpToStart));
     big_sleep();
     // re-pause since reaching end doesn't actually pause
-    overwatch.send(&ctrl(P));
-    rename(record_dir);
-    print!("{:?} done. ", player);
-    std::io::stdout().flush().expect("Couldn't flush stdout");
+    game.window.send(&keys.get(Action::Pause));
+    let recording = Recording {
+        files: rename(record_dir, label),
+        offset,
+        length: now() - started - away,
+        timed_out,
+        partial,
+        drifts,
+        focus_lost,
+        kills: kills.into_events(),
+        round_ends,
+    };
+    if interrupted {
+        info!("{:?} interrupted.", player);
+        return Perspective::Interrupted(Some(recording));
+    }
+    if left {
+        let after = (last_seen - started).saturating_sub(away);
+        info!("{:?} left after {}s.", player, after.as_secs());
+        return Perspective::Left { recording, after };
+    }
+    info!("{:?} done.", player);
+    Perspective::Recorded(recording)
+}
+
+/// Pauses the replay and goes back to `to` while the clock is showing, for picking up where we left off.
+/// Returns where we think we ended up.
+fn pause_and_seek(game: &mut Live, session: &Session, to: Duration) -> Duration {
+    let keys = &session.config.keys;
+    game.window.send(&keys.get(Action::ToggleControls));
+    big_sleep();
+    if !game.obs.get_screenshot::<InReplay>().is_definitely_paused() {
+        game.window.send(&keys.get(Action::Pause));
+    }
+    game.window.send(&keys.get(Action::JumpToStart));
+    big_sleep();
+    let position = seek(
+        game.obs,
+        game.window,
+        to,
+        &session.config,
+        session.clock.as_ref(),
+    );
+    game.window.send(&keys.get(Action::ToggleControls));
+    position
+}
+
+/// How long a dry run has skipped sleeping for, in milliseconds.
+static SKIPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Sleeps, except in a dry run, where there's no game to wait for.
+pub fn sleep_for(how_long: Duration) {
+    if dry_run() {
+        SKIPPED.fetch_add(how_long.as_millis() as u64, Ordering::SeqCst);
+    } else {
+        sleep(how_long);
+    }
+}
+
+/// The time for timing the game by, which in a dry run still counts the sleeps it skipped,
+/// so anything waiting on the game times out after as many loops as it would for real.
+fn now() -> Instant {
+    Instant::now() + Duration::from_millis(SKIPPED.load(Ordering::SeqCst))
 }
 
 pub fn small_sleep() {
-    sleep(Duration::from_millis(200));
+    sleep_for(Duration::from_millis(200));
 }
 
 pub fn med_sleep() {
-    sleep(Duration::from_secs(1));
+    sleep_for(Duration::from_secs(1));
 }
 
 pub fn big_sleep() {
-    sleep(Duration::from_secs(2));
+    sleep_for(Duration::from_secs(2));
+}
+
+/// Runs ffmpeg and panics if it fails, or in a dry run just prints what it would have run.
+fn run_ffmpeg(command: &mut Command, failure: &str) {
+    if dry_run() {
+        info!("[dry run] {:?}", command);
+        return;
+    }
+    debug!("ffmpeg command={:?}", command);
+    let result = command.status().expect(failure);
+    if !result.success() {
+        panic!(
+            "ffmpeg failed with code {}",
+            result.code().map_or("?".to_string(), |x| x.to_string())
+        )
+    }
 }
 
 const MUX_ALL: bool = false;
 
 /// Multiplex all those pieces into a video file with one track for each video,
 /// plus one track with a whole matrix overview exclusively for the purpose of flexing.
-fn mux(record_dir: PathBuf) {
+fn mux(record_dir: PathBuf, team_size: u8) {
     if !has_ffmpeg() {
         return;
     }
-    let cameras = read_dir(&record_dir)
+    let mut cameras = read_dir(&record_dir)
         .expect("Couldn't read record dir for muxing")
         .filter_map(|x| x.ok())
-        .map(|x| x.file_name())
-        .filter(|x| x != "final.mkv" && x != "mosaic.mkv")
+        .filter(|x| is_recording(&x.file_name()))
+        .map(|x| {
+            let modified = x.metadata().and_then(|x| x.modified()).ok();
+            (modified, x.file_name())
+        })
+        .collect::<Vec<_>>();
+    // keep them in the order they were recorded, whatever they were named
+    cameras.sort();
+    let cameras = cameras.into_iter().map(|(_, x)| x).collect::<Vec<_>>();
+    let truncated = truncated_recordings(&record_dir);
+    let truncated = cameras
+        .iter()
+        .map(|x| truncated.contains(x))
         .collect::<Vec<_>>();
     let mut inputs = cameras
         .into_iter()
         .flat_map(|x| vec![OsString::from("-i"), x])
         .collect::<Vec<_>>();
 
-    println!("Building mosaic...");
-    let filter = r"
-        nullsrc=size=1920x720:r=60 [base];
-        [0:v] setpts=PTS-STARTPTS, scale=640x360 [upperleft];
-        [1:v] setpts=PTS-STARTPTS, scale=640x360 [uppermiddle];
-        [2:v] setpts=PTS-STARTPTS, scale=640x360 [upperright];
-        [3:v] setpts=PTS-STARTPTS, scale=640x360 [lowerleft];
-        [4:v] setpts=PTS-STARTPTS, scale=640x360 [lowermiddle];
-        [5:v] setpts=PTS-STARTPTS, scale=640x360 [lowerright];
-        [base][upperleft] overlay=shortest=1 [tmp1];
-        [tmp1][uppermiddle] overlay=shortest=1:x=640 [tmp2];
-        [tmp2][upperright] overlay=shortest=1:x=1280 [tmp3];
-        [tmp3][lowerleft] overlay=shortest=1:y=360 [tmp4];
-        [tmp4][lowermiddle] overlay=shortest=1:y=360:x=640 [tmp5];
-        [tmp5][lowerright] overlay=shortest=1:y=360:x=1280
-    ";
-    let result = Command::new("ffmpeg")
-        .args(&["-y", "-hide_banner", "-v", "warning", "-nostats"])
-        .args(&inputs)
-        .arg("-filter_complex")
-        .arg(filter)
-        .args(&[
-            "-c:v", "libx264", "-preset", "veryfast", "-crf", "18", "-an",
-        ])
-        .arg("mosaic.mkv")
-        .current_dir(&record_dir)
-        .status()
-        .expect("Couldn't build mosaic");
-    if !result.success() {
-        panic!(
-            "ffmpeg failed with code {}",
-            result.code().map_or("?".to_string(), |x| x.to_string())
-        )
-    }
-    if !RUNNING.load(Ordering::SeqCst) {
+    info!("Building mosaic...");
+    let filter = mosaic_filter(&truncated, team_size as usize);
+    run_ffmpeg(
+        Command::new("ffmpeg")
+            .args(&["-y", "-hide_banner", "-v", "warning", "-nostats"])
+            .args(&inputs)
+            .arg("-filter_complex")
+            .arg(&filter)
+            .args(&[
+                "-c:v", "libx264", "-preset", "veryfast", "-crf", "18", "-an",
+            ])
+            .arg("mosaic.mkv")
+            .current_dir(&record_dir),
+        "Couldn't build mosaic",
+    );
+    // a dry run never made the mosaic, so there's nothing to move or merge
+    if !RUNNING.load(Ordering::SeqCst) || dry_run() {
         return;
     }
 
@@ -405,7 +1449,7 @@ fn mux(record_dir: PathBuf) {
         OsString::from("mosaic.mkv"),
     ]);
 
-    println!("Merging...");
+    info!("Merging...");
     let out_name = {
         let mut path = PathBuf::from("..");
         path.push(
@@ -415,34 +1459,112 @@ fn mux(record_dir: PathBuf) {
         );
         path.with_extension("mkv")
     };
+    let camera_count = inputs.len() / 2 - 1;
     let maps = (0..(inputs.len() / 2))
         .flat_map(|x| vec!["-map".to_string(), format!("{}", x)])
         .collect::<Vec<_>>();
-    let result = Command::new("ffmpeg")
-        .args(&["-y", "-hide_banner", "-v", "warning", "-stats"])
-        .args(inputs)
-        .args(&[
-            "-filter_complex",
-            "[0:a][1:a][2:a][3:a][4:a][5:a] amix=inputs=6",
-        ])
-        .args(maps)
-        .args(&["-c:v", "copy", "-c:a", "aac"])
-        .arg(&out_name)
-        .current_dir(&record_dir)
-        .status()
-        .expect("Couldn't mux");
-    if !result.success() {
-        panic!(
-            "ffmpeg failed with code {}",
-            result.code().map_or("?".to_string(), |x| x.to_string())
-        )
-    }
+    run_ffmpeg(
+        Command::new("ffmpeg")
+            .args(&["-y", "-hide_banner", "-v", "warning", "-stats"])
+            .args(inputs)
+            .arg("-filter_complex")
+            .arg(format!(
+                "{} amix=inputs={}",
+                (0..camera_count)
+                    .map(|x| format!("[{}:a]", x))
+                    .collect::<String>(),
+                camera_count
+            ))
+            .args(maps)
+            .args(&["-c:v", "copy", "-c:a", "aac"])
+            .arg(&out_name)
+            .current_dir(&record_dir),
+        "Couldn't mux",
+    );
     if !RUNNING.load(Ordering::SeqCst) {
         return;
     }
 }
 
+/// Highlights only have the one recording, so there's nothing to lay out, just a single track to move out.
+fn mux_single(record_dir: PathBuf) {
+    if !has_ffmpeg() {
+        return;
+    }
+    let camera = read_dir(&record_dir)
+        .expect("Couldn't read record dir for muxing")
+        .filter_map(|x| x.ok())
+        .map(|x| x.file_name())
+        .find(|x| is_recording(x))
+        .expect("Couldn't find the highlight recording");
+    let mut dest = PathBuf::from(record_dir.parent().expect("No path parent"));
+    dest.push(format!(
+        "done_highlight_{}.mkv",
+        record_dir
+            .file_name()
+            .and_then(|x| x.to_str())
+            .expect("Failed to get directory name")
+    ));
+    run_ffmpeg(
+        Command::new("ffmpeg")
+            .args(&["-y", "-hide_banner", "-v", "warning", "-nostats", "-i"])
+            .arg(camera)
+            .args(&["-c", "copy"])
+            .arg(dest)
+            .current_dir(&record_dir),
+        "Couldn't copy highlight",
+    );
+}
+
+/// Lay out however many cameras we have in a grid of 640x360 tiles, based on the team size:
+/// one team's worth is split over two rows (so 6v6 makes the classic 3x2 grid),
+/// and more than that gets a row per team.
+/// Cameras that were cut short go black once they end, rather than ending the whole mosaic.
+fn mosaic_filter(truncated: &[bool], team_size: usize) -> String {
+    const WIDTH: usize = 640;
+    const HEIGHT: usize = 360;
+    let camera_count = truncated.len();
+    let all_truncated = truncated.iter().all(|&x| x);
+    let columns = if camera_count <= team_size {
+        (team_size + 1) / 2
+    } else {
+        team_size
+    };
+    let rows = (camera_count + columns - 1) / columns;
+    let mut filter = format!(
+        "nullsrc=size={}x{}:r=60 [base];\n",
+        columns * WIDTH,
+        rows * HEIGHT
+    );
+    for i in 0..camera_count {
+        filter += &format!(
+            "[{}:v] setpts=PTS-STARTPTS, scale={}x{} [camera{}];\n",
+            i, WIDTH, HEIGHT, i
+        );
+    }
+    let mut last = "base".to_string();
+    for (i, &cut_short) in truncated.iter().enumerate() {
+        let x = (i % columns) * WIDTH;
+        let y = (i / columns) * HEIGHT;
+        let ending = if cut_short && !all_truncated {
+            "eof_action=pass"
+        } else {
+            "shortest=1"
+        };
+        filter += &format!("[{}][camera{}] overlay={}:x={}:y={}", last, i, ending, x, y);
+        if i + 1 < camera_count {
+            last = format!("tmp{}", i);
+            filter += &format!(" [{}];\n", last);
+        }
+    }
+    filter
+}
+
 fn has_ffmpeg() -> bool {
+    // a dry run prints the commands whether or not they'd work
+    if dry_run() {
+        return true;
+    }
     let result = Command::new("ffmpeg")
         .arg("-version")
         .stdin(Stdio::null())
@@ -453,17 +1575,38 @@ fn has_ffmpeg() -> bool {
     result.success()
 }
 
-pub fn rename(record_dir: &PathBuf) {
+/// Whether a file in the record dir is one of OBS's recordings, as opposed to our own output.
+fn is_recording(file_name: &OsStr) -> bool {
+    const EXTENSIONS: &[&str] = &["flv", "mp4", "mov", "mkv", "ts", "m3u8"];
+    if file_name == "final.mkv" || file_name == "mosaic.mkv" {
+        return false;
+    }
+    Path::new(file_name)
+        .extension()
+        .and_then(|x| x.to_str())
+        .map_or(false, |x| EXTENSIONS.contains(&x))
+}
+
+/// Marks the new recordings as done, and returns their new names.
+pub fn rename(record_dir: &PathBuf, label: Option<&str>) -> Vec<String> {
     let not_done = read_dir(record_dir)
         .expect("Couldn't read record dir for renaming")
         .filter_map(|x| x.ok())
-        .filter_map(|x| x.file_name().into_string().ok())
+        .map(|x| x.file_name())
+        .filter(|x| is_recording(x))
+        .filter_map(|x| x.into_string().ok())
         .filter(|x| !x.starts_with("done_"));
+    let mut result = vec![];
     for file in not_done {
         let src = record_dir.join(&file);
-        let dest = record_dir.join(format!("done_{}", &file));
-        ::std::fs::rename(src, dest).expect("Couldn't rename");
+        let dest = match label {
+            Some(label) => format!("done_{}_{}", label, &file),
+            None => format!("done_{}", &file),
+        };
+        ::std::fs::rename(src, record_dir.join(&dest)).expect("Couldn't rename");
+        result.push(dest);
     }
+    result
 }
 
 pub fn timestamp() -> String {
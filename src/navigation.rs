@@ -0,0 +1,552 @@
+use crate::image::{Anywhere, Screen};
+use crate::macros::Macros;
+use crate::obs::OBSClient;
+use crate::window::*;
+use crate::ReplaySource;
+use std::time::Duration;
+
+/// Where we are in the game, on the way from the main menu to recording a replay and back.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum State {
+    MainMenu,
+    /// The replays tab of the replays & highlights screen.
+    Replays,
+    /// The highlights tab of the same screen.
+    Highlights,
+    /// In a replay, but not recording.
+    Spectating,
+    Recording,
+    /// Recording a highlight, which plays straight from the highlights tab.
+    PlayingHighlight,
+}
+
+/// Everything the navigator does to the game, so it can be driven by a simulated one in tests.
+pub trait Game {
+    fn send(&mut self, key: &Key);
+    fn click(&mut self, x: i32, y: i32);
+    fn wait(&mut self, how_long: Duration);
+    fn look(&mut self) -> Screen;
+    fn start_recording(&mut self);
+    fn stop_recording(&mut self);
+
+    fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.send(&Char(c));
+        }
+    }
+}
+
+/// The real game, watched through OBS.
+pub struct Live<'a> {
+    pub window: &'a Window,
+    pub obs: &'a mut OBSClient,
+}
+
+impl Game for Live<'_> {
+    fn send(&mut self, key: &Key) {
+        self.window.send(key);
+    }
+
+    fn click(&mut self, x: i32, y: i32) {
+        self.window.click(x, y);
+    }
+
+    fn wait(&mut self, how_long: Duration) {
+        crate::sleep_for(how_long);
+    }
+
+    fn look(&mut self) -> Screen {
+        self.obs.get_screenshot::<Anywhere>().screen()
+    }
+
+    fn start_recording(&mut self) {
+        self.obs.start_recording();
+    }
+
+    fn stop_recording(&mut self) {
+        self.obs.stop_recording();
+    }
+
+    fn type_text(&mut self, text: &str) {
+        self.window.type_text(text);
+    }
+}
+
+const LONG: Duration = Duration::from_secs(2);
+/// How many times to try leaving a replay before giving up.
+const EXIT_ATTEMPTS: u32 = 3;
+
+/// Keeps track of where we are, and only moves between states that make sense,
+/// checking with a screenshot wherever one can tell us whether it worked.
+/// The moves themselves are macros, so they can be changed without a new build.
+pub struct Navigator {
+    state: State,
+    macros: Macros,
+}
+
+impl Navigator {
+    /// We start where the user was told to leave the game.
+    pub fn new(macros: Macros) -> Navigator {
+        Navigator {
+            state: State::MainMenu,
+            macros,
+        }
+    }
+
+    // asking for a transition from the wrong state is a bug, not something to recover from
+    fn require(&self, allowed: &[State], action: &str) {
+        assert!(
+            allowed.contains(&self.state),
+            "Can't {} from {:?}",
+            action,
+            self.state
+        );
+    }
+
+    /// Gets from the main menu or either tab to one of the tabs of the replays & highlights screen.
+    pub fn open_tab<G: Game>(&mut self, game: &mut G, tab: State) -> Result<(), String> {
+        assert!(
+            tab == State::Replays || tab == State::Highlights,
+            "{:?} isn't a tab",
+            tab
+        );
+        if self.state == tab {
+            return Ok(());
+        }
+        self.require(
+            &[State::MainMenu, State::Replays, State::Highlights],
+            "open a tab",
+        );
+        if self.state == State::MainMenu {
+            self.macros.run(game, "open_replays_screen", &[])?;
+        }
+        match tab {
+            State::Replays => self.macros.run(game, "open_replays_tab", &[])?,
+            _ => self.macros.run(game, "open_highlights_tab", &[])?,
+        }
+        // either tab could be empty, so all we can check is that we're not somewhere else entirely
+        match game.look() {
+            Screen::ReplayList | Screen::Unknown => {
+                self.state = tab;
+                Ok(())
+            }
+            screen => Err(format!("expected the {:?} tab but saw {:?}", tab, screen)),
+        }
+    }
+
+    /// Opens a replay from the replays tab, and waits for it to load.
+    pub fn open_replay<G: Game>(
+        &mut self,
+        game: &mut G,
+        replay: &ReplaySource,
+    ) -> Result<(), String> {
+        self.require(&[State::Replays], "open a replay");
+        match replay {
+            ReplaySource::Index(index) => {
+                // the cursor stays on whichever row failed last, and clicking the tab puts it back on top
+                self.macros.run(game, "open_replays_tab", &[])?;
+                let index = index.to_string();
+                self.macros.run(game, "open_replay", &[("index", &index)])?;
+            }
+            ReplaySource::Code(code) => {
+                self.macros.run(game, "import_replay", &[("code", code)])?;
+            }
+            ReplaySource::Highlight(_) => unreachable!("highlights go through play_highlight"),
+        }
+        match game.look() {
+            Screen::ErrorDialog => {
+                // dismissing it leaves us on the replays tab, ready for the next one
+                self.macros.run(game, "dismiss_error", &[])?;
+                Err("the replay viewer couldn't play it".to_string())
+            }
+            // only the list itself, filled in from the top row, counts as still being on it
+            Screen::ReplayList => Err("it never opened".to_string()),
+            // whoever the camera starts on might not be showing their badge yet
+            Screen::Replay | Screen::Unknown => {
+                self.state = State::Spectating;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn start_recording<G: Game>(&mut self, game: &mut G) {
+        self.require(&[State::Spectating], "start recording");
+        game.start_recording();
+        self.state = State::Recording;
+    }
+
+    pub fn stop_recording<G: Game>(&mut self, game: &mut G) {
+        self.require(&[State::Recording], "stop recording");
+        game.stop_recording();
+        self.state = State::Spectating;
+    }
+
+    /// Picks a highlight from the highlights tab and records it playing.
+    pub fn play_highlight<G: Game>(&mut self, game: &mut G, index: u8) -> Result<(), String> {
+        self.require(&[State::Highlights], "play a highlight");
+        // the cursor stays wherever the last highlight left it, and clicking the tab puts it back on top
+        self.macros.run(game, "open_highlights_tab", &[])?;
+        let index = index.to_string();
+        self.macros
+            .run(game, "pick_highlight", &[("index", &index)])?;
+        game.start_recording();
+        // wait a bit so OBS can catch up
+        game.wait(LONG);
+        if let Err(e) = self.macros.run(game, "play_highlight", &[]) {
+            game.stop_recording();
+            return Err(e);
+        }
+        if game.look() == Screen::ErrorDialog {
+            game.stop_recording();
+            game.wait(LONG);
+            self.macros.run(game, "dismiss_error", &[])?;
+            return Err("the highlight viewer couldn't play it".to_string());
+        }
+        self.state = State::PlayingHighlight;
+        Ok(())
+    }
+
+    /// Once a highlight is over we're dropped back on the highlights tab by ourselves.
+    pub fn finish_highlight<G: Game>(&mut self, game: &mut G) {
+        self.require(&[State::PlayingHighlight], "finish a highlight");
+        game.stop_recording();
+        self.state = State::Highlights;
+    }
+
+    /// Quits the replay back to the main menu, trying again if we're somehow still in it.
+    pub fn exit_replay<G: Game>(&mut self, game: &mut G) -> Result<(), String> {
+        self.require(&[State::Spectating], "exit a replay");
+        for _ in 0..EXIT_ATTEMPTS {
+            self.macros.run(game, "exit_replay", &[])?;
+            if game.look() != Screen::Replay {
+                self.state = State::MainMenu;
+                return Ok(());
+            }
+        }
+        Err("couldn't get out of the replay".to_string())
+    }
+
+    /// After something went wrong, works out where we really are and gets back to a state we know.
+    pub fn recover<G: Game>(&mut self, game: &mut G) -> Result<(), String> {
+        if self.state == State::Recording || self.state == State::PlayingHighlight {
+            game.stop_recording();
+            self.state = match self.state {
+                State::Recording => State::Spectating,
+                _ => State::Highlights,
+            };
+        }
+        match game.look() {
+            Screen::ErrorDialog => {
+                self.macros.run(game, "dismiss_error", &[])?;
+                if self.state != State::Highlights {
+                    self.state = State::Replays;
+                }
+                Ok(())
+            }
+            Screen::ReplayList => {
+                self.state = State::Replays;
+                Ok(())
+            }
+            Screen::Replay => {
+                self.state = State::Spectating;
+                self.exit_replay(game)
+            }
+            // that's what every menu we could be on looks like, so as long as we think we're on one, fine
+            Screen::Unknown => match self.state {
+                State::MainMenu | State::Replays | State::Highlights => Ok(()),
+                state => Err(format!(
+                    "thought we were {:?} but can't tell where we are",
+                    state
+                )),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where the simulated game really is.
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Place {
+        MainMenu,
+        Replays,
+        Highlights,
+        Error(State),
+        Replay,
+        EscapeMenu,
+    }
+
+    /// Just enough of the game's menus to check the navigator finds its way around them.
+    struct Simulated {
+        place: Place,
+        ups: u8,
+        replays: usize,
+        /// Whether the next replay or highlight opened shows an error instead.
+        broken: bool,
+        /// Whether picking a replay does nothing at all.
+        frozen: bool,
+        /// How many more times leaving a replay does nothing.
+        stubborn: u32,
+        recording: bool,
+        /// How far down the list the cursor is, from the tab at the top.
+        cursor: usize,
+        /// Which rows were opened, in order.
+        opened: Vec<usize>,
+    }
+
+    impl Simulated {
+        fn new() -> Simulated {
+            Simulated {
+                place: Place::MainMenu,
+                ups: 0,
+                replays: 3,
+                broken: false,
+                frozen: false,
+                stubborn: 0,
+                recording: false,
+                cursor: 0,
+                opened: vec![],
+            }
+        }
+    }
+
+    impl Game for Simulated {
+        fn send(&mut self, key: &Key) {
+            match (self.place, key) {
+                (Place::Replays, Down) | (Place::Highlights, Down) => self.cursor += 1,
+                (Place::Replays, Space) | (Place::Highlights, Space) => {
+                    self.opened.push(self.cursor)
+                }
+                (Place::MainMenu, Space) => self.cursor = 0,
+                _ => (),
+            }
+            self.place = match (self.place, key) {
+                (Place::MainMenu, Up) | (Place::EscapeMenu, Up) => {
+                    self.ups += 1;
+                    self.place
+                }
+                (Place::MainMenu, Space) if self.ups == 4 => Place::Replays,
+                (Place::Replays, Space) if self.frozen => Place::Replays,
+                (Place::Replays, Space) if self.broken => Place::Error(State::Replays),
+                (Place::Replays, Space) => Place::Replay,
+                (Place::Highlights, Space) if self.broken => Place::Error(State::Highlights),
+                (Place::Error(State::Highlights), Escape) => Place::Highlights,
+                (Place::Error(_), Escape) => Place::Replays,
+                (Place::Replay, Escape) => Place::EscapeMenu,
+                (Place::EscapeMenu, Space) if self.ups == 2 && self.stubborn > 0 => {
+                    self.stubborn -= 1;
+                    Place::Replay
+                }
+                (Place::EscapeMenu, Space) if self.ups == 2 => Place::MainMenu,
+                (Place::EscapeMenu, Escape) => Place::Replay,
+                (place, _) => place,
+            };
+            if *key == Space || *key == Escape {
+                self.ups = 0;
+            }
+        }
+
+        fn click(&mut self, x: i32, _y: i32) {
+            if x == 220 || x == 380 {
+                self.cursor = 0;
+            }
+            self.place = match (self.place, x) {
+                (Place::Replays, 220) | (Place::Highlights, 220) => Place::Highlights,
+                (Place::Replays, 380) | (Place::Highlights, 380) => Place::Replays,
+                (place, _) => place,
+            };
+        }
+
+        fn wait(&mut self, _how_long: Duration) {}
+
+        fn look(&mut self) -> Screen {
+            match self.place {
+                Place::Replays if self.replays > 0 => Screen::ReplayList,
+                Place::Error(_) => Screen::ErrorDialog,
+                Place::Replay => Screen::Replay,
+                _ => Screen::Unknown,
+            }
+        }
+
+        fn start_recording(&mut self) {
+            self.recording = true;
+        }
+
+        fn stop_recording(&mut self) {
+            self.recording = false;
+        }
+    }
+
+    #[test]
+    fn records_a_replay_and_gets_back_to_the_menu() {
+        let mut game = Simulated::new();
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.open_tab(&mut game, State::Replays).unwrap();
+        assert_eq!(game.place, Place::Replays);
+        navigator
+            .open_replay(&mut game, &ReplaySource::Index(1))
+            .unwrap();
+        assert_eq!(game.place, Place::Replay);
+        navigator.start_recording(&mut game);
+        assert!(game.recording);
+        navigator.stop_recording(&mut game);
+        assert!(!game.recording);
+        navigator.exit_replay(&mut game).unwrap();
+        assert_eq!(navigator.state, State::MainMenu);
+        assert_eq!(game.place, Place::MainMenu);
+        // and can do it all again
+        navigator.open_tab(&mut game, State::Replays).unwrap();
+        assert_eq!(game.place, Place::Replays);
+    }
+
+    #[test]
+    fn broken_replay_leaves_us_on_the_replays_tab() {
+        let mut game = Simulated::new();
+        game.broken = true;
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.open_tab(&mut game, State::Replays).unwrap();
+        let result = navigator.open_replay(&mut game, &ReplaySource::Index(0));
+        assert!(result.is_err());
+        assert_eq!(navigator.state, State::Replays);
+        assert_eq!(game.place, Place::Replays);
+    }
+
+    #[test]
+    fn opens_the_right_replay_after_a_broken_one() {
+        let mut game = Simulated::new();
+        game.broken = true;
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.open_tab(&mut game, State::Replays).unwrap();
+        assert!(navigator
+            .open_replay(&mut game, &ReplaySource::Index(2))
+            .is_err());
+        game.broken = false;
+        navigator.open_tab(&mut game, State::Replays).unwrap();
+        navigator
+            .open_replay(&mut game, &ReplaySource::Index(1))
+            .unwrap();
+        assert_eq!(game.opened, vec![2, 1]);
+    }
+
+    #[test]
+    fn never_opened_is_an_error() {
+        let mut game = Simulated::new();
+        game.frozen = true;
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.open_tab(&mut game, State::Replays).unwrap();
+        let result = navigator.open_replay(&mut game, &ReplaySource::Index(0));
+        assert_eq!(result, Err("it never opened".to_string()));
+        assert_eq!(navigator.state, State::Replays);
+    }
+
+    #[test]
+    fn tries_again_to_leave_a_replay() {
+        let mut game = Simulated::new();
+        game.stubborn = 1;
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.open_tab(&mut game, State::Replays).unwrap();
+        navigator
+            .open_replay(&mut game, &ReplaySource::Index(0))
+            .unwrap();
+        navigator.exit_replay(&mut game).unwrap();
+        assert_eq!(game.place, Place::MainMenu);
+    }
+
+    #[test]
+    fn gives_up_leaving_eventually() {
+        let mut game = Simulated::new();
+        game.stubborn = EXIT_ATTEMPTS;
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.open_tab(&mut game, State::Replays).unwrap();
+        navigator
+            .open_replay(&mut game, &ReplaySource::Index(0))
+            .unwrap();
+        assert!(navigator.exit_replay(&mut game).is_err());
+        assert_eq!(navigator.state, State::Spectating);
+    }
+
+    #[test]
+    fn highlights_record_from_their_tab() {
+        let mut game = Simulated::new();
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.open_tab(&mut game, State::Highlights).unwrap();
+        assert_eq!(game.place, Place::Highlights);
+        navigator.play_highlight(&mut game, 2).unwrap();
+        assert!(game.recording);
+        navigator.finish_highlight(&mut game);
+        assert!(!game.recording);
+        assert_eq!(navigator.state, State::Highlights);
+        // switching tabs doesn't go through the main menu
+        navigator.open_tab(&mut game, State::Replays).unwrap();
+        assert_eq!(game.place, Place::Replays);
+    }
+
+    #[test]
+    fn highlights_in_a_row_count_from_the_top() {
+        let mut game = Simulated::new();
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.open_tab(&mut game, State::Highlights).unwrap();
+        navigator.play_highlight(&mut game, 2).unwrap();
+        navigator.finish_highlight(&mut game);
+        navigator.open_tab(&mut game, State::Highlights).unwrap();
+        navigator.play_highlight(&mut game, 3).unwrap();
+        navigator.finish_highlight(&mut game);
+        assert_eq!(game.opened, vec![2, 3]);
+    }
+
+    #[test]
+    fn broken_highlight_stops_recording() {
+        let mut game = Simulated::new();
+        game.broken = true;
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.open_tab(&mut game, State::Highlights).unwrap();
+        assert!(navigator.play_highlight(&mut game, 0).is_err());
+        assert!(!game.recording);
+        assert_eq!(game.place, Place::Highlights);
+    }
+
+    #[test]
+    fn recovers_from_a_stray_error_dialog() {
+        let mut game = Simulated::new();
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.open_tab(&mut game, State::Replays).unwrap();
+        navigator
+            .open_replay(&mut game, &ReplaySource::Index(0))
+            .unwrap();
+        navigator.start_recording(&mut game);
+        // the replay got kicked back out to the list with an error
+        game.place = Place::Error(State::Replays);
+        navigator.recover(&mut game).unwrap();
+        assert!(!game.recording);
+        assert_eq!(navigator.state, State::Replays);
+        assert_eq!(game.place, Place::Replays);
+    }
+
+    #[test]
+    fn recovers_by_leaving_a_replay_we_thought_we_had_left() {
+        let mut game = Simulated::new();
+        game.place = Place::Replay;
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.recover(&mut game).unwrap();
+        assert_eq!(navigator.state, State::MainMenu);
+        assert_eq!(game.place, Place::MainMenu);
+    }
+
+    #[test]
+    fn cant_recover_when_lost() {
+        let mut game = Simulated::new();
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.state = State::Spectating;
+        assert!(navigator.recover(&mut game).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't start recording from MainMenu")]
+    fn refuses_impossible_transitions() {
+        let mut game = Simulated::new();
+        let mut navigator = Navigator::new(Macros::builtin());
+        navigator.start_recording(&mut game);
+    }
+}
@@ -1,26 +1,118 @@
 use crate::image::{OWContext, Screenshot};
 
+use image::{DynamicImage, ImageFormat, RgbImage};
 use serde_json::Value;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use websocket::client::sync::Client;
 use websocket::stream::sync::TcpStream;
 use websocket::ws::dataframe::DataFrame;
 use websocket::{ClientBuilder, Message};
 
+/// Stands in for OBS in a dry run, answering screenshots with saved frames instead of the game.
+struct Fake {
+    frames: Vec<PathBuf>,
+    next_frame: usize,
+    rec_folder: String,
+    recordings: usize,
+}
+
+impl Fake {
+    /// Where a dry run finds the frames to show the detectors, in name order.
+    const FRAMES: &'static str = "dry_run_frames";
+
+    fn new() -> Fake {
+        let mut frames = fs::read_dir(Fake::FRAMES)
+            .map(|x| {
+                x.filter_map(|x| x.ok())
+                    .map(|x| x.path())
+                    .filter(|x| x.extension().map_or(false, |x| x == "png"))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        frames.sort();
+        if frames.is_empty() {
+            info!(
+                "[dry run] no frames in {}, so every screenshot will be black",
+                Fake::FRAMES
+            );
+        }
+        let rec_folder = std::env::temp_dir().join("OWReplayRenderer dry run");
+        fs::create_dir_all(&rec_folder).expect("Failed to create dry run folder");
+        Fake {
+            frames,
+            next_frame: 0,
+            rec_folder: rec_folder.to_string_lossy().into_owned(),
+            recordings: 0,
+        }
+    }
+
+    // the frames go round and round, so a short set still covers a whole replay
+    fn screenshot(&mut self) -> Vec<u8> {
+        if self.frames.is_empty() {
+            let mut data = vec![];
+            DynamicImage::ImageRgb8(RgbImage::new(1920, 1080))
+                .write_to(&mut data, ImageFormat::PNG)
+                .expect("Couldn't make a blank frame");
+            return data;
+        }
+        let frame = &self.frames[self.next_frame % self.frames.len()];
+        self.next_frame += 1;
+        info!("[dry run] showing {}", frame.display());
+        fs::read(frame).expect("Couldn't read dry run frame")
+    }
+
+    fn respond(&mut self, request: &Value) -> Value {
+        match request["request-type"].as_str().unwrap_or_default() {
+            "GetRecordingFolder" => json!({ "rec-folder": self.rec_folder }),
+            "SetRecordingFolder" => {
+                self.rec_folder = request["rec-folder"]
+                    .as_str()
+                    .expect("Recording folder was not a string!")
+                    .to_string();
+                json!({})
+            }
+            "GetCurrentScene" => json!({ "name": "dry run" }),
+            "TakeSourceScreenshot" => json!({
+                "img": format!("data:image/png;base64,{}", base64::encode(&self.screenshot())),
+            }),
+            // leave an empty file behind, so there's something to rename and mux afterwards
+            "StopRecording" => {
+                self.recordings += 1;
+                let file = format!("dry run {}.mkv", self.recordings);
+                fs::write(Path::new(&self.rec_folder).join(file), [])
+                    .expect("Couldn't write dry run recording");
+                json!({})
+            }
+            _ => json!({}),
+        }
+    }
+}
+
+enum Connection {
+    Real(Client<TcpStream>),
+    Fake(Fake),
+}
+
 pub struct OBSClient {
-    client: Client<TcpStream>,
+    connection: Connection,
     orig_dir: Option<String>,
 }
 
 impl OBSClient {
     pub fn new() -> OBSClient {
-        let client = ClientBuilder::new("ws://localhost:4444")
-            .unwrap()
-            .connect_insecure()
-            .unwrap();
+        let connection = if crate::dry_run() {
+            Connection::Fake(Fake::new())
+        } else {
+            let client = ClientBuilder::new("ws://localhost:4444")
+                .unwrap()
+                .connect_insecure()
+                .unwrap();
+            Connection::Real(client)
+        };
         let mut result = OBSClient {
-            client,
+            connection,
             orig_dir: None,
         };
         result.send_request(json!({
@@ -30,23 +122,38 @@ impl OBSClient {
         result
     }
 
-    fn recv(&mut self) -> Value {
-        let response = self.client.recv_message().unwrap();
+    fn recv(client: &mut Client<TcpStream>) -> Value {
+        let response = client.recv_message().unwrap();
         let response: Value = serde_json::from_slice(response.take_payload().as_slice()).unwrap();
         // ignore heartbeats or other update events
         if response.as_object().unwrap().contains_key("update-type") {
-            return self.recv();
+            return OBSClient::recv(client);
         }
         response
     }
 
     fn send_request(&mut self, mut request: Value) -> Value {
+        let client = match self.connection {
+            Connection::Real(ref mut x) => x,
+            Connection::Fake(ref mut fake) => {
+                info!("[dry run] OBS {}", request);
+                return fake.respond(&request);
+            }
+        };
+        let request_type = request["request-type"].clone();
         let message_id = "x";
         request["message-id"] = Value::String(message_id.to_string());
         let request = Message::text(serde_json::to_string(&request).unwrap());
-        self.client.send_message(&request).unwrap();
-        let response = self.recv();
+        let started = Instant::now();
+        client.send_message(&request).unwrap();
+        let response = OBSClient::recv(client);
         let status = response["status"].as_str().unwrap();
+        debug!(
+            "obs request={} status={} ms={}",
+            request_type,
+            status,
+            started.elapsed().as_millis()
+        );
         if status == "error" {
             panic!("OBS WebSocket failure: {}", response["error"]);
         }
@@ -65,6 +172,18 @@ impl OBSClient {
         }));
     }
 
+    pub fn pause_recording(&mut self) {
+        self.send_request(json!({
+            "request-type": "PauseRecording",
+        }));
+    }
+
+    pub fn resume_recording(&mut self) {
+        self.send_request(json!({
+            "request-type": "ResumeRecording",
+        }));
+    }
+
     fn get_output_dir(&mut self) -> String {
         let response = self.send_request(json!({
             "request-type": "GetRecordingFolder",
@@ -0,0 +1,139 @@
+use crate::config::Config;
+use crate::image::{Badge, InReplay, Screenshot};
+use crate::keybindings::Action;
+use crate::obs::OBSClient;
+use crate::slots::both_sides;
+use crate::window::*;
+use crate::{big_sleep, SAME_PLAYER};
+
+fn look(obs: &mut OBSClient) -> Screenshot<InReplay> {
+    big_sleep();
+    obs.get_screenshot::<InReplay>()
+}
+
+/// How one keybind did.
+enum Check {
+    Works,
+    Wrong(String),
+    /// We couldn't tell, since the slot it spectates might just be empty.
+    Unsure(String),
+}
+
+fn check(problem: Option<String>) -> Check {
+    problem.map_or(Check::Works, Check::Wrong)
+}
+
+/// Presses every replay viewer keybind in whatever replay is open, checking each one
+/// does what it should on screen, and says exactly which ones don't.
+/// Returns whether they all worked, not counting player keys that might have landed on empty slots.
+pub fn check_keys(obs: &mut OBSClient, overwatch: &Window, config: &Config) -> bool {
+    let keys = &config.keys;
+    let mut results: Vec<(Action, Check)> = vec![];
+    overwatch.focus();
+
+    // the controls go first, since the rest need them showing to be seen
+    let toggle = keys.get(Action::ToggleControls);
+    let before = look(obs).controls_shown();
+    overwatch.send(&toggle);
+    let after = look(obs).controls_shown();
+    let problem = if before == after {
+        let state = if after { "shown" } else { "hidden" };
+        Some(format!("the controls stayed {}", state))
+    } else {
+        None
+    };
+    results.push((Action::ToggleControls, check(problem)));
+    if !after {
+        overwatch.send(&toggle);
+    }
+
+    // the pause button flips between pause and play
+    let pause = keys.get(Action::Pause);
+    let before = look(obs).is_definitely_paused();
+    overwatch.send(&pause);
+    let after = look(obs).is_definitely_paused();
+    let problem = if before == after {
+        Some("the pause button didn't change".to_string())
+    } else {
+        None
+    };
+    results.push((Action::Pause, check(problem)));
+    // stay paused, so the timeline only moves when we move it
+    if !after {
+        overwatch.send(&pause);
+    }
+
+    // the timeline moves forward, then all the way back
+    let forward = keys.get(Action::ReplayForward);
+    let before = look(obs).timeline_progress();
+    overwatch.send(&forward);
+    let after = look(obs).timeline_progress();
+    let problem = if after <= before {
+        Some("the timeline didn't move forward".to_string())
+    } else {
+        None
+    };
+    results.push((Action::ReplayForward, check(problem)));
+
+    overwatch.send(&keys.get(Action::JumpToStart));
+    let after = look(obs).timeline_progress();
+    let problem = if after > 0.01 {
+        Some(format!(
+            "the timeline is still {:.0}% of the way through",
+            after * 100.0
+        ))
+    } else {
+        None
+    };
+    results.push((Action::JumpToStart, check(problem)));
+
+    // and every player key lands on somebody new
+    let mut seen: Vec<Badge> = vec![];
+    for slot in both_sides(config.team_size) {
+        let number = function_number(&slot).expect("Slots are always F-keys");
+        overwatch.send(&keys.spectate(&slot));
+        let screenshot = look(obs);
+        let badge = screenshot.badge();
+        let result = if !screenshot.has_player() {
+            Check::Unsure("nobody's badge showed up".to_string())
+        } else if seen
+            .iter()
+            .any(|x| x.same_player_score(&badge) >= SAME_PLAYER)
+        {
+            Check::Unsure("the camera stayed on someone we'd already seen".to_string())
+        } else {
+            Check::Works
+        };
+        seen.push(badge);
+        results.push((Action::SpectatePlayer(number), result));
+    }
+
+    println!("Keybinds:");
+    for (action, result) in &results {
+        let key = keys.get(*action);
+        match result {
+            Check::Works => println!("  {:?} ({}): ok", action, key),
+            Check::Wrong(problem) => println!("  {:?} ({}): WRONG, {}", action, key, problem),
+            Check::Unsure(problem) => println!(
+                "  {:?} ({}): can't tell, {} (fine if the slot is empty)",
+                action, key, problem
+            ),
+        }
+    }
+    let all_ok = results
+        .iter()
+        .all(|(_, result)| !matches!(result, Check::Wrong(_)));
+    let unsure = results
+        .iter()
+        .any(|(_, result)| matches!(result, Check::Unsure(_)));
+    if all_ok && unsure {
+        println!("Every keybind we could check works! To check the rest, open a replay with every slot filled.");
+    } else if all_ok {
+        println!("All keybinds work!");
+    } else {
+        println!(
+            r#"Fix those in Overwatch's settings or the "keys" in config.json, then check again."#
+        );
+    }
+    all_ok
+}
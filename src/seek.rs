@@ -0,0 +1,143 @@
+use crate::config::Config;
+use crate::image::{ClockDigits, InReplay};
+use crate::keybindings::Action;
+use crate::obs::OBSClient;
+use crate::window::*;
+use std::time::Duration;
+
+/// The part of each replay to record.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TimeRange {
+    pub start: Duration,
+    /// `None` means until the game ends.
+    pub end: Option<Duration>,
+    /// Only these rounds, counting from 1, whenever they turn out to be.
+    /// Until a recording has seen when they start and end, `start` and `end` don't apply.
+    pub rounds: Option<(u8, u8)>,
+}
+
+/// The most rounds a game could go to, even with a lot of overtime.
+const MAX_ROUNDS: u8 = 9;
+
+// "4:30" or "270"
+fn parse_time(text: &str) -> Result<Duration, String> {
+    let pieces: Vec<&str> = text.splitn(2, ':').collect();
+    let seconds = match pieces.as_slice() {
+        [s] => s.parse::<u64>().map_err(|e| e.to_string())?,
+        [m, s] => {
+            let m = m.parse::<u64>().map_err(|e| e.to_string())?;
+            let s = s.parse::<u64>().map_err(|e| e.to_string())?;
+            if s >= 60 {
+                return Err(format!("{} is not a valid time", text));
+            }
+            m * 60 + s
+        }
+        _ => unreachable!(),
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+impl TimeRange {
+    /// Parses "4:30-9:00", "4:30-" (to the end), "-9:00" (from the start), "round 2", or "rounds 2-3".
+    /// An empty string means the whole game.
+    pub fn parse(text: &str) -> Result<TimeRange, String> {
+        let text = text.trim().to_lowercase();
+        if text.is_empty() {
+            return Ok(TimeRange::default());
+        }
+        let rounds = text
+            .strip_prefix("rounds")
+            .or_else(|| text.strip_prefix("round"));
+        if let Some(rounds) = rounds {
+            let rounds = crate::parse_range(rounds.trim(), MAX_ROUNDS)?;
+            return Ok(TimeRange {
+                rounds: Some(rounds),
+                ..TimeRange::default()
+            });
+        }
+        let pieces: Vec<&str> = text.splitn(2, '-').map(|x| x.trim()).collect();
+        let (start, end) = match pieces.as_slice() {
+            [start, end] => (*start, *end),
+            _ => return Err(format!("{} needs a - between the start and end", text)),
+        };
+        let start = if start.is_empty() {
+            Duration::from_secs(0)
+        } else {
+            parse_time(start)?
+        };
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(parse_time(end)?)
+        };
+        if end.map_or(false, |end| end <= start) {
+            return Err(format!("{} ends before it starts", text));
+        }
+        Ok(TimeRange {
+            start,
+            end,
+            rounds: None,
+        })
+    }
+
+    /// When rounds `first` to `last` are, going by when each round ended in a recording that watched them.
+    /// `None` if that recording never got as far as the start of them.
+    pub fn for_rounds(first: u8, last: u8, ends: &[Duration]) -> Option<TimeRange> {
+        let start = match first {
+            1 => Duration::ZERO,
+            _ => *ends.get(first as usize - 2)?,
+        };
+        // if the game ended first, that's the end of them too
+        let end = ends.get(last as usize - 1).cloned();
+        Some(TimeRange {
+            start,
+            end,
+            rounds: None,
+        })
+    }
+}
+
+/// How many times we'll nudge forward to make up for Replay Forward falling short.
+const MAX_CORRECTIONS: u32 = 10;
+
+/// Skips forward from the start of a paused replay to `to`, one Replay Forward of `config.forward_step` at a time.
+/// If we can read the clock, we check it and keep going until we're within a step,
+/// otherwise we trust that every step is the same size.
+/// Returns where we think we ended up.
+pub fn seek(
+    obs: &mut OBSClient,
+    overwatch: &Window,
+    to: Duration,
+    config: &Config,
+    clock: Option<&ClockDigits>,
+) -> Duration {
+    let step = config.forward_step;
+    let forward = config.keys.get(Action::ReplayForward);
+    let steps = (to.as_secs() / step.as_secs().max(1)) as u32;
+    for _ in 0..steps {
+        overwatch.send(&forward);
+    }
+    let mut position = step * steps;
+    let clock = match clock {
+        Some(x) => x,
+        None => return position,
+    };
+    crate::big_sleep();
+    for _ in 0..MAX_CORRECTIONS {
+        match obs.get_screenshot::<InReplay>().read_clock(clock) {
+            Some(now) => {
+                position = now;
+                if now + step > to {
+                    break;
+                }
+                overwatch.send(&forward);
+                crate::big_sleep();
+            }
+            None => {
+                warn!("Couldn't read the replay clock, so the start time may be off.");
+                break;
+            }
+        }
+    }
+    position
+}
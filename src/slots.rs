@@ -0,0 +1,106 @@
+use crate::window::*;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Side {
+    Red,
+    Blue,
+}
+
+/// The most players a team can have, since there are only twelve F-keys.
+pub const MAX_TEAM_SIZE: u8 = 6;
+
+impl Side {
+    /// The F-keys that spectate this side's players: blue is the first `team_size` of them,
+    /// red is the `team_size` after that (so F1-F5 and F6-F10 for 5v5, F1-F6 and F7-F12 for 6v6).
+    pub fn players(self, team_size: u8) -> Vec<Key> {
+        let first = match self {
+            Side::Blue => 1,
+            Side::Red => team_size + 1,
+        };
+        (first..first + team_size)
+            .map(|x| function_key(x).expect("Team too big for the F-keys"))
+            .collect()
+    }
+
+    /// Which side an F-key spectates, if it's one of the first `team_size * 2`.
+    pub fn of(slot: &Key, team_size: u8) -> Option<Side> {
+        [Side::Blue, Side::Red]
+            .iter()
+            .copied()
+            .find(|x| x.players(team_size).contains(slot))
+    }
+}
+
+pub fn both_sides(team_size: u8) -> Vec<Key> {
+    let mut result = Side::Blue.players(team_size);
+    result.extend(Side::Red.players(team_size));
+    result
+}
+
+/// Whose perspectives to record from each replay.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Selection {
+    /// Everyone on the side we guessed we were on.
+    Team,
+    /// Only the slots where one of our badges was found.
+    Me,
+    Blue,
+    Red,
+    /// Both teams.
+    All,
+    /// Exactly these F-keys, in this order.
+    Slots(Vec<Key>),
+}
+
+impl Selection {
+    /// Parses "team", "me", "blue", "red", "all", or a list of F-keys like "F1,F3,F9".
+    /// An empty string means "team".
+    pub fn parse(text: &str, team_size: u8) -> Result<Selection, String> {
+        let text = text.trim().to_lowercase();
+        match text.as_str() {
+            "" | "team" => return Ok(Selection::Team),
+            "me" | "me only" => return Ok(Selection::Me),
+            "blue" => return Ok(Selection::Blue),
+            "red" => return Ok(Selection::Red),
+            "all" | "both" => return Ok(Selection::All),
+            _ => (),
+        }
+        let mut result = vec![];
+        for piece in text.split(',').map(|x| x.trim()) {
+            let key = piece
+                .strip_prefix('f')
+                .and_then(|x| x.parse::<u8>().ok())
+                .filter(|&x| x <= team_size * 2)
+                .and_then(function_key)
+                .ok_or_else(|| format!("{} is not one of F1-F{}", piece, team_size * 2))?;
+            if !result.contains(&key) {
+                result.push(key);
+            }
+        }
+        Ok(Selection::Slots(result))
+    }
+
+    /// Works out which F-keys to record, given the side we guessed (if any)
+    /// and the slots where our badges were found.
+    /// `None` means we'd need a side guess we don't have.
+    pub fn resolve(&self, side: Option<Side>, found: &[Key], team_size: u8) -> Option<Vec<Key>> {
+        match self {
+            Selection::Team => side.map(|x| x.players(team_size)),
+            Selection::Me => {
+                let result = both_sides(team_size)
+                    .into_iter()
+                    .filter(|x| found.contains(x))
+                    .collect::<Vec<_>>();
+                if result.is_empty() {
+                    None
+                } else {
+                    Some(result)
+                }
+            }
+            Selection::Blue => Some(Side::Blue.players(team_size)),
+            Selection::Red => Some(Side::Red.players(team_size)),
+            Selection::All => Some(both_sides(team_size)),
+            Selection::Slots(keys) => Some(keys.clone()),
+        }
+    }
+}
@@ -1,47 +1,155 @@
-use winapi::shared::minwindef::{BOOL, FALSE, LPARAM, TRUE};
-use winapi::shared::ntdef::LPSTR;
-use winapi::shared::windef::HWND;
+use crate::coords::{to_absolute, to_desktop, Rect};
+use regex::Regex;
+use serde_json::Value;
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, LPARAM, TRUE};
+use winapi::shared::windef::{HWND, POINT, RECT};
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::OpenProcess;
+use winapi::um::winbase::QueryFullProcessImageNameW;
+use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
 use winapi::um::winuser::{
-    EnumWindows, GetForegroundWindow, GetWindowTextA, GetWindowTextLengthA, SendInput, INPUT,
+    ClientToScreen, EnumWindows, GetClassNameW, GetClientRect, GetForegroundWindow,
+    GetSystemMetrics, GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId, IsIconic,
+    IsWindowVisible, SendInput, SetForegroundWindow, SetProcessDPIAware, ShowWindow, INPUT,
     INPUT_KEYBOARD, INPUT_MOUSE, KEYEVENTF_KEYUP, MOUSEEVENTF_ABSOLUTE, MOUSEEVENTF_LEFTDOWN,
-    MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MOVE, VK_DOWN, VK_ESCAPE, VK_F1, VK_F10, VK_F11, VK_F12, VK_F2,
-    VK_F3, VK_F4, VK_F5, VK_F6, VK_F7, VK_F8, VK_F9, VK_LCONTROL, VK_LEFT, VK_RIGHT, VK_SPACE,
-    VK_TAB, VK_UP,
+    MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MOVE, MOUSEEVENTF_VIRTUALDESK, SM_CXVIRTUALSCREEN,
+    SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN, SW_RESTORE, VK_BACK, VK_DELETE,
+    VK_DOWN, VK_END, VK_ESCAPE, VK_F1, VK_F10, VK_F11, VK_F12, VK_F2, VK_F3, VK_F4, VK_F5, VK_F6,
+    VK_F7, VK_F8, VK_F9, VK_HOME, VK_INSERT, VK_LCONTROL, VK_LEFT, VK_LMENU, VK_LSHIFT, VK_MENU,
+    VK_NEXT, VK_NUMPAD0, VK_OEM_1, VK_OEM_2, VK_OEM_3, VK_OEM_4, VK_OEM_5, VK_OEM_6, VK_OEM_7,
+    VK_OEM_COMMA, VK_OEM_MINUS, VK_OEM_PERIOD, VK_OEM_PLUS, VK_PRIOR, VK_RETURN, VK_RIGHT,
+    VK_SPACE, VK_TAB, VK_UP,
 };
 
-#[derive(Debug, Copy, Clone)]
-enum Target {
-    Overwatch,
+/// How to recognize a window. Everything that's given has to match.
+#[derive(Clone, Debug)]
+pub struct WindowPattern {
+    pub title: Option<Regex>,
+    pub class: Option<String>,
+    /// The executable's file name, like "Overwatch.exe".
+    pub process: Option<String>,
 }
 
-impl Target {
-    fn pattern(&self) -> &'static str {
-        match self {
-            Target::Overwatch => "Overwatch",
+impl WindowPattern {
+    /// The game goes by its executable, which stays the same whatever language the title is in.
+    pub fn overwatch() -> WindowPattern {
+        WindowPattern {
+            title: None,
+            class: None,
+            process: Some("Overwatch.exe".to_string()),
+        }
+    }
+
+    /// Reads something like `{"title": "^Overwatch", "class": "TankWindowClass", "process": "Overwatch.exe"}`.
+    pub fn parse(value: &Value) -> Result<WindowPattern, String> {
+        let field = |name: &str| -> Result<Option<String>, String> {
+            match &value[name] {
+                Value::Null => Ok(None),
+                Value::String(x) => Ok(Some(x.clone())),
+                _ => Err(format!("window {} must be a string", name)),
+            }
+        };
+        let title = match field("title")? {
+            Some(x) => Some(Regex::new(&x).map_err(|e| e.to_string())?),
+            None => None,
+        };
+        let result = WindowPattern {
+            title,
+            class: field("class")?,
+            process: field("process")?,
+        };
+        if result.title.is_none() && result.class.is_none() && result.process.is_none() {
+            return Err("window needs at least one of title, class, or process".to_string());
+        }
+        Ok(result)
+    }
+
+    fn matches(&self, candidate: &Candidate) -> bool {
+        self.title
+            .as_ref()
+            .map_or(true, |x| x.is_match(&candidate.title))
+            && self.class.as_ref().map_or(true, |x| *x == candidate.class)
+            && self.process.as_ref().map_or(true, |x| {
+                candidate
+                    .process
+                    .as_ref()
+                    .map_or(false, |process| process.eq_ignore_ascii_case(x))
+            })
+    }
+}
+
+impl fmt::Display for WindowPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut pieces = vec![];
+        if let Some(title) = &self.title {
+            pieces.push(format!("title /{}/", title));
+        }
+        if let Some(class) = &self.class {
+            pieces.push(format!("class {}", class));
+        }
+        if let Some(process) = &self.process {
+            pieces.push(format!("process {}", process));
         }
+        write!(f, "{}", pieces.join(", "))
     }
 }
 
-struct WindowSearch {
-    handle: Option<HWND>,
-    target: Target,
+/// A visible window that might be the one we want.
+struct Candidate {
+    handle: HWND,
+    title: String,
+    class: String,
+    process: Option<String>,
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "\"{}\" (class {}, process {})",
+            self.title,
+            self.class,
+            self.process.as_deref().unwrap_or("unknown")
+        )
+    }
 }
 
 pub struct Window {
     handle: HWND,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Key {
-    P,
-    N,
     Left,
     Right,
     Up,
     Down,
     Space,
     Tab,
+    Enter,
     Escape,
+    Backspace,
+    Insert,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// A letter or a digit.
+    Char(char),
+    /// A digit on the number pad.
+    Numpad(u8),
+    Minus,
+    Equals,
+    Comma,
+    Period,
+    Slash,
+    Semicolon,
+    Quote,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+    Backtick,
     F1,
     F2,
     F3,
@@ -55,10 +163,14 @@ pub enum Key {
     F11,
     F12,
     Ctrl(Box<Key>),
+    Shift(Box<Key>),
+    Alt(Box<Key>),
 }
 
+use std::fmt;
+use std::sync::atomic::Ordering;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use winapi::ctypes::c_int;
 pub use Key::*;
 
@@ -66,33 +178,146 @@ pub fn ctrl<K: Into<Box<Key>>>(key: K) -> Key {
     Ctrl(key.into())
 }
 
+pub fn shift<K: Into<Box<Key>>>(key: K) -> Key {
+    Shift(key.into())
+}
+
+pub fn alt<K: Into<Box<Key>>>(key: K) -> Key {
+    Alt(key.into())
+}
+
+/// The F-key with the given number, if there is one.
+pub fn function_key(number: u8) -> Option<Key> {
+    let keys = [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12];
+    keys.get((number as usize).checked_sub(1)?).cloned()
+}
+
+/// Which F-key this is, if it is one.
+pub fn function_number(key: &Key) -> Option<u8> {
+    (1..=12).find(|&x| function_key(x).as_ref() == Some(key))
+}
+
+impl Key {
+    /// Looks up a single key by name, like "Left", "PageUp", "F5", "Num3", "Q", or ";".
+    fn named(name: &str) -> Option<Key> {
+        let name = name.to_lowercase();
+        let key = match name.as_str() {
+            "left" => Left,
+            "right" => Right,
+            "up" => Up,
+            "down" => Down,
+            "space" => Space,
+            "tab" => Tab,
+            "enter" | "return" => Enter,
+            "escape" | "esc" => Escape,
+            "backspace" => Backspace,
+            "insert" | "ins" => Insert,
+            "delete" | "del" => Delete,
+            "home" => Home,
+            "end" => End,
+            "pageup" | "pgup" => PageUp,
+            "pagedown" | "pgdn" => PageDown,
+            "-" | "minus" => Minus,
+            "=" | "equals" => Equals,
+            "," | "comma" => Comma,
+            "." | "period" => Period,
+            "/" | "slash" => Slash,
+            ";" | "semicolon" => Semicolon,
+            "'" | "quote" => Quote,
+            "[" | "leftbracket" => LeftBracket,
+            "]" | "rightbracket" => RightBracket,
+            "\\" | "backslash" => Backslash,
+            "`" | "backtick" => Backtick,
+            _ => {
+                if let Some(number) = name.strip_prefix('f').filter(|x| !x.is_empty()) {
+                    return function_key(number.parse().ok()?);
+                }
+                if let Some(digit) = name
+                    .strip_prefix("numpad")
+                    .or_else(|| name.strip_prefix("num"))
+                {
+                    return digit.parse().ok().filter(|&x| x <= 9).map(Numpad);
+                }
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_alphanumeric() => Char(c.to_ascii_uppercase()),
+                    _ => return None,
+                }
+            }
+        };
+        Some(key)
+    }
+
+    /// Parses a key chord like "Ctrl+Shift+Left": any of Ctrl, Shift, and Alt, then one key.
+    pub fn parse(text: &str) -> Result<Key, String> {
+        let mut pieces: Vec<&str> = text.split('+').map(|x| x.trim()).collect();
+        let name = pieces.pop().unwrap_or_default();
+        let mut result = Key::named(name).ok_or_else(|| format!("{} isn't a key we know", name))?;
+        for modifier in pieces.iter().rev() {
+            result = match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => ctrl(result),
+                "shift" => shift(result),
+                "alt" => alt(result),
+                _ => {
+                    return Err(format!(
+                        "{} in {} isn't Ctrl, Shift, or Alt",
+                        modifier, text
+                    ))
+                }
+            };
+        }
+        Ok(result)
+    }
+}
+
+fn keydown(vk: c_int) -> INPUT {
+    let mut result: INPUT = unsafe { std::mem::zeroed() };
+    result.type_ = INPUT_KEYBOARD;
+    unsafe {
+        let info = result.u.ki_mut();
+        info.wVk = vk as u16;
+    }
+    result
+}
+
+fn keyup(vk: c_int) -> INPUT {
+    let mut result: INPUT = unsafe { std::mem::zeroed() };
+    result.type_ = INPUT_KEYBOARD;
+    unsafe {
+        let info = result.u.ki_mut();
+        info.wVk = vk as u16;
+        info.dwFlags = KEYEVENTF_KEYUP;
+    }
+    result
+}
+
+/// Written the same way `Key::parse` reads it, like "Ctrl+Shift+Left".
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Char(c) => write!(f, "{}", c),
+            Numpad(n) => write!(f, "Num{}", n),
+            Ctrl(k) => write!(f, "Ctrl+{}", k),
+            Shift(k) => write!(f, "Shift+{}", k),
+            Alt(k) => write!(f, "Alt+{}", k),
+            k => write!(f, "{:?}", k),
+        }
+    }
+}
+
 impl IntoIterator for Key {
     type Item = INPUT;
     type IntoIter = <Vec<INPUT> as IntoIterator>::IntoIter;
     #[allow(non_snake_case)]
     fn into_iter(self) -> Self::IntoIter {
-        fn keydown(vk: c_int) -> INPUT {
-            let mut result: INPUT = unsafe { std::mem::zeroed() };
-            result.type_ = INPUT_KEYBOARD;
-            unsafe {
-                let info = result.u.ki_mut();
-                info.wVk = vk as u16;
-            }
-            result
-        }
-        fn keyup(vk: c_int) -> INPUT {
-            let mut result: INPUT = unsafe { std::mem::zeroed() };
-            result.type_ = INPUT_KEYBOARD;
-            unsafe {
-                let info = result.u.ki_mut();
-                info.wVk = vk as u16;
-                info.dwFlags = KEYEVENTF_KEYUP;
-            }
-            result
+        fn chord(modifier: c_int, key: Key) -> <Vec<INPUT> as IntoIterator>::IntoIter {
+            std::iter::once(keydown(modifier))
+                .chain(key)
+                .chain(std::iter::once(keyup(modifier)))
+                .collect::<Vec<_>>()
+                .into_iter()
         }
         let vk = match self {
-            P => 'P' as i32,
-            N => 'N' as i32,
             Left => VK_LEFT,
             Right => VK_RIGHT,
             Up => VK_UP,
@@ -100,6 +325,29 @@ impl IntoIterator for Key {
             Escape => VK_ESCAPE,
             Space => VK_SPACE,
             Tab => VK_TAB,
+            Enter => VK_RETURN,
+            Backspace => VK_BACK,
+            Insert => VK_INSERT,
+            Delete => VK_DELETE,
+            Home => VK_HOME,
+            End => VK_END,
+            PageUp => VK_PRIOR,
+            PageDown => VK_NEXT,
+            // the virtual key codes for letters and digits are just their uppercase ASCII
+            Char(c) => c.to_ascii_uppercase() as i32,
+            Numpad(n) => VK_NUMPAD0 + n as c_int,
+            // these are where they are on a US keyboard, and wherever the layout puts them otherwise
+            Minus => VK_OEM_MINUS,
+            Equals => VK_OEM_PLUS,
+            Comma => VK_OEM_COMMA,
+            Period => VK_OEM_PERIOD,
+            Slash => VK_OEM_2,
+            Semicolon => VK_OEM_1,
+            Quote => VK_OEM_7,
+            LeftBracket => VK_OEM_4,
+            RightBracket => VK_OEM_6,
+            Backslash => VK_OEM_5,
+            Backtick => VK_OEM_3,
             F1 => VK_F1,
             F2 => VK_F2,
             F3 => VK_F3,
@@ -112,58 +360,99 @@ impl IntoIterator for Key {
             F10 => VK_F10,
             F11 => VK_F11,
             F12 => VK_F12,
-            Ctrl(k) => {
-                let before = keydown(VK_LCONTROL);
-                let after = keyup(VK_LCONTROL);
-                return std::iter::once(before)
-                    .chain(k.into_iter())
-                    .chain(std::iter::once(after))
-                    .collect::<Vec<_>>()
-                    .into_iter();
-            }
+            Ctrl(k) => return chord(VK_LCONTROL, *k),
+            Shift(k) => return chord(VK_LSHIFT, *k),
+            Alt(k) => return chord(VK_LMENU, *k),
         };
         vec![keydown(vk), keyup(vk)].into_iter()
     }
 }
 
-unsafe extern "system" fn has_title(win: HWND, arg: LPARAM) -> BOOL {
-    let result = arg as *mut WindowSearch;
-    let size = GetWindowTextLengthA(win);
-    if size == 0 {
-        return TRUE;
+// the file name of the executable that owns the window, if we're allowed to look
+unsafe fn process_name(win: HWND) -> Option<String> {
+    let mut id: DWORD = 0;
+    GetWindowThreadProcessId(win, &mut id);
+    let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, id);
+    if process.is_null() {
+        return None;
     }
-    let mut raw_title = vec![0i8; size as usize + 1];
-    GetWindowTextA(win, &mut raw_title[0] as LPSTR, size + 1);
-    let raw_title = raw_title.iter().map(|&x| x as u8).collect::<Vec<_>>();
-    let title = String::from_utf8_lossy(&raw_title[0..(size as usize)]);
-    if title == (*result).target.pattern() {
-        (*result).handle = Some(win);
-        return FALSE;
+    let mut path = vec![0u16; 1024];
+    let mut size = path.len() as DWORD;
+    let ok = QueryFullProcessImageNameW(process, 0, path.as_mut_ptr(), &mut size);
+    CloseHandle(process);
+    if ok == FALSE {
+        return None;
     }
+    let path = String::from_utf16_lossy(&path[0..size as usize]);
+    path.rsplit('\\').next().map(|x| x.to_string())
+}
+
+unsafe extern "system" fn add_candidate(win: HWND, arg: LPARAM) -> BOOL {
+    let result = arg as *mut Vec<Candidate>;
+    let size = GetWindowTextLengthW(win);
+    if size == 0 || IsWindowVisible(win) == FALSE {
+        return TRUE;
+    }
+    let mut title = vec![0u16; size as usize + 1];
+    let size = GetWindowTextW(win, title.as_mut_ptr(), size + 1);
+    let title = String::from_utf16_lossy(&title[0..size as usize]);
+    let mut class = vec![0u16; 256];
+    let size = GetClassNameW(win, class.as_mut_ptr(), class.len() as i32);
+    let class = String::from_utf16_lossy(&class[0..size as usize]);
+    (*result).push(Candidate {
+        handle: win,
+        title,
+        class,
+        process: process_name(win),
+    });
     TRUE
 }
 
 impl Window {
-    fn find(target: Target) -> Window {
-        let mut result: Box<WindowSearch> = Box::new(WindowSearch {
-            handle: None,
-            target,
-        });
-        unsafe {
-            EnumWindows(
-                Some(has_title),
-                result.as_mut() as *mut WindowSearch as LPARAM,
+    /// Waits up to `timeout` for a window matching the pattern to show up.
+    /// If none does, the error lists every window we saw instead, to help fix the pattern.
+    pub fn find(pattern: &WindowPattern, timeout: Duration) -> Result<Window, String> {
+        if crate::dry_run() {
+            info!(
+                "[dry run] pretending the game is the window with {}",
+                pattern
             );
+            return Ok(Window {
+                handle: std::ptr::null_mut(),
+            });
         }
-        Window {
-            handle: result
-                .handle
-                .expect(&format!("Couldn't find {:?} window", target)),
+        // so window sizes come back in real pixels, not scaled down for high DPI
+        unsafe {
+            SetProcessDPIAware();
+        }
+        let started = Instant::now();
+        loop {
+            let mut candidates: Vec<Candidate> = vec![];
+            unsafe {
+                EnumWindows(
+                    Some(add_candidate),
+                    &mut candidates as *mut Vec<Candidate> as LPARAM,
+                );
+            }
+            if let Some(x) = candidates.iter().find(|x| pattern.matches(x)) {
+                debug!("window found {}", x);
+                return Ok(Window { handle: x.handle });
+            }
+            if started.elapsed() >= timeout {
+                let candidates = candidates
+                    .iter()
+                    .map(|x| format!("  {}", x))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                return Err(format!(
+                    "Couldn't find a window with {} after {}s. These are the windows that are open:\n{}",
+                    pattern,
+                    timeout.as_secs(),
+                    candidates
+                ));
+            }
+            sleep(Duration::from_secs(1));
         }
-    }
-
-    pub fn overwatch() -> Window {
-        Window::find(Target::Overwatch)
     }
 
     unsafe fn is_focused(&self) -> bool {
@@ -179,23 +468,94 @@ impl Window {
         crate::small_sleep();
     }
 
+    pub fn has_focus(&self) -> bool {
+        crate::dry_run() || unsafe { self.is_focused() }
+    }
+
+    /// Brings the window to the front ourselves, rather than waiting for the user to.
+    pub fn focus(&self) {
+        if crate::dry_run() {
+            info!("[dry run] bring the game to the front");
+            return;
+        }
+        unsafe {
+            if !self.is_focused() {
+                debug!("focus minimized={}", IsIconic(self.handle) != FALSE);
+                if IsIconic(self.handle) != FALSE {
+                    ShowWindow(self.handle, SW_RESTORE);
+                }
+                // Windows only lets whoever got the last input take the foreground,
+                // and a tap of Alt is enough to count without doing anything else
+                self.send_input(vec![keydown(VK_MENU), keyup(VK_MENU)]);
+                SetForegroundWindow(self.handle);
+                sleep(Duration::from_millis(100));
+            }
+        }
+        self.await_focus();
+    }
+
+    /// Waits for the user to switch back if they've switched away, so nothing we send goes astray.
     pub fn await_focus(&self) {
-        while unsafe { !self.is_focused() } {
+        if self.has_focus() {
+            return;
+        }
+        warn!("Overwatch isn't focused, so everything's on hold until you switch back to it.");
+        while !self.has_focus() {
+            if !crate::RUNNING.load(Ordering::SeqCst) {
+                return;
+            }
             sleep(Duration::from_millis(100));
         }
+        info!("Overwatch is focused again, carrying on.");
+        // give it a moment to start listening again
+        crate::small_sleep();
     }
 
+    // where the game draws, on the desktop
+    unsafe fn client_rect(&self) -> Rect {
+        let mut rect: RECT = std::mem::zeroed();
+        GetClientRect(self.handle, &mut rect);
+        let mut origin = POINT { x: 0, y: 0 };
+        ClientToScreen(self.handle, &mut origin);
+        Rect {
+            left: origin.x,
+            top: origin.y,
+            width: rect.right - rect.left,
+            height: rect.bottom - rect.top,
+        }
+    }
+
+    /// Clicks where `x` and `y` would be on a 1080p screenshot of the game,
+    /// wherever the window is and however big it is.
     pub fn click(&self, x: i32, y: i32) {
+        if crate::dry_run() {
+            info!("[dry run] click {} {}", x, y);
+            return;
+        }
+        self.await_focus();
         unsafe {
             if self.is_focused() {
-                let x = (x * 65535) / 1920;
-                let y = (y * 65535) / 1080;
+                let (desktop_x, desktop_y) = to_desktop(x, y, &self.client_rect());
+                let desktop = Rect {
+                    left: GetSystemMetrics(SM_XVIRTUALSCREEN),
+                    top: GetSystemMetrics(SM_YVIRTUALSCREEN),
+                    width: GetSystemMetrics(SM_CXVIRTUALSCREEN),
+                    height: GetSystemMetrics(SM_CYVIRTUALSCREEN),
+                };
+                debug!(
+                    "click x={} y={} desktop_x={} desktop_y={}",
+                    x, y, desktop_x, desktop_y
+                );
+                let (x, y) = to_absolute(desktop_x, desktop_y, &desktop);
                 let mut result0: INPUT = std::mem::zeroed();
                 result0.type_ = INPUT_MOUSE;
                 let info = result0.u.mi_mut();
                 info.dx = x;
                 info.dy = y;
-                info.dwFlags = MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_MOVE | MOUSEEVENTF_LEFTDOWN;
+                info.dwFlags = MOUSEEVENTF_ABSOLUTE
+                    | MOUSEEVENTF_VIRTUALDESK
+                    | MOUSEEVENTF_MOVE
+                    | MOUSEEVENTF_LEFTDOWN;
                 let mut result1: INPUT = std::mem::zeroed();
                 result1.type_ = INPUT_MOUSE;
                 let info = result1.u.mi_mut();
@@ -206,12 +566,96 @@ impl Window {
         }
     }
 
+    /// Types out letters and digits one at a time.
+    pub fn type_text(&self, text: &str) {
+        for c in text.chars() {
+            self.send(&Char(c));
+        }
+    }
+
     pub fn send(&self, key: &Key) {
+        if crate::dry_run() {
+            info!("[dry run] press {}", key);
+            return;
+        }
+        self.await_focus();
         unsafe {
             if self.is_focused() {
+                debug!("press key={}", key);
                 let inputs: Vec<INPUT> = key.clone().into_iter().collect();
                 self.send_input(inputs);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chords_are_parsed() {
+        assert_eq!(Key::parse("Left"), Ok(Left));
+        assert_eq!(Key::parse("ctrl+p"), Ok(ctrl(Char('P'))));
+        assert_eq!(Key::parse("Ctrl+Shift+Left"), Ok(ctrl(shift(Left))));
+        assert_eq!(Key::parse("Alt + Num3"), Ok(alt(Numpad(3))));
+        assert_eq!(Key::parse("F12"), Ok(F12));
+    }
+
+    #[test]
+    fn unknown_keys_and_modifiers_are_refused() {
+        assert_eq!(
+            Key::parse("Super+Left"),
+            Err("Super in Super+Left isn't Ctrl, Shift, or Alt".to_string())
+        );
+        assert_eq!(
+            Key::parse("Ctrl+Nope"),
+            Err("Nope isn't a key we know".to_string())
+        );
+        for text in ["F13", "F0", "Num10", "", "Ctrl+"].iter() {
+            assert!(Key::parse(text).is_err(), "{} should be refused", text);
+        }
+    }
+
+    #[test]
+    fn keys_read_back_the_way_they_are_written() {
+        let mut keys = vec![
+            Left,
+            Right,
+            Up,
+            Down,
+            Space,
+            Tab,
+            Enter,
+            Escape,
+            Backspace,
+            Insert,
+            Delete,
+            Home,
+            End,
+            PageUp,
+            PageDown,
+            Char('Q'),
+            Char('7'),
+            Numpad(0),
+            Minus,
+            Equals,
+            Comma,
+            Period,
+            Slash,
+            Semicolon,
+            Quote,
+            LeftBracket,
+            RightBracket,
+            Backslash,
+            Backtick,
+            ctrl(shift(Left)),
+            alt(Numpad(9)),
+            shift(ctrl(alt(Char('P')))),
+        ];
+        keys.extend((1..=12).filter_map(function_key));
+        for key in keys {
+            assert_eq!(Key::parse(&key.to_string()), Ok(key));
+        }
+    }
+}